@@ -0,0 +1,63 @@
+use common::intcode::assembler::assemble;
+use common::intcode::loader;
+use common::intcode::{create_computer, Computer, ComputerState, Level, Value};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+fn day02_program() -> Vec<Value> {
+    let mut memory = loader::parse(include_str!("../../day02/input.txt")).expect("Invalid input");
+    memory[1] = 12;
+    memory[2] = 2;
+    memory
+}
+
+/// Sums 1..=100000 in a loop
+fn long_running_program() -> Vec<Value> {
+    let source = "
+        loop:   add sum, counter, sum
+                add counter, #-1, counter
+                jnz counter, #loop
+                out sum
+                halt
+        counter: data 100000
+        sum:     data 0
+    ";
+    assemble(Level::Day05, source).expect("Failed assembling")
+}
+
+fn compare(c: &mut Criterion, name: &str, level: Level, memory: Vec<Value>) {
+    let computer = create_computer(level, memory);
+    let mut group = c.benchmark_group(name);
+
+    group.bench_function("step", |b| {
+        b.iter_batched(
+            || computer.clone(),
+            |mut computer: Computer| {
+                assert_eq!(computer.run_until_end(), Ok(ComputerState::Finished));
+                computer
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("decoded", |b| {
+        b.iter_batched(
+            || computer.clone(),
+            |mut computer: Computer| {
+                assert_eq!(computer.run_decoded(), Ok(ComputerState::Finished));
+                computer
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn day02(c: &mut Criterion) {
+    compare(c, "day02", Level::Day02, day02_program());
+}
+
+fn long_running(c: &mut Criterion) {
+    compare(c, "long_running", Level::Day05, long_running_program());
+}
+
+criterion_group!(benches, day02, long_running);
+criterion_main!(benches);
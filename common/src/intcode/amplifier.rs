@@ -0,0 +1,243 @@
+use crate::intcode::{create_computer, Computer, ComputerState, IntcodeError, Level, Value};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum AmplifierError {
+    #[error("Amplifier {index}: {error}")]
+    Computer {
+        index: usize,
+        #[source]
+        error: IntcodeError,
+    },
+    #[error("All amplifiers are waiting for input")]
+    Deadlock,
+    #[error("Last amplifier did not output a signal")]
+    NoSignal,
+}
+
+/// Computers wired in series. Output of every computer is the input of the next one.
+/// With feedback, output of the last computer is fed back into the first one
+#[derive(Debug, Clone)]
+pub struct AmplifierChain {
+    computers: Vec<Computer>,
+    feedback: bool,
+    signal: Option<Value>,
+}
+
+impl AmplifierChain {
+    pub fn new(computers: Vec<Computer>, feedback: bool) -> Self {
+        Self {
+            computers,
+            feedback,
+            signal: None,
+        }
+    }
+
+    /// One computer running program per phase setting, each getting its phase as first input
+    pub fn with_phases(
+        level: Level,
+        program: Vec<Value>,
+        phases: &[Value],
+        feedback: bool,
+    ) -> Self {
+        let template = create_computer(level, program);
+        let computers = phases
+            .iter()
+            .map(|phase| {
+                let mut computer = template.clone();
+                computer.push_input(*phase);
+                computer
+            })
+            .collect();
+        Self::new(computers, feedback)
+    }
+
+    pub fn get_computers(&self) -> &[Computer] {
+        &self.computers
+    }
+
+    pub fn is_feedback(&self) -> bool {
+        self.feedback
+    }
+
+    /// Last value the last computer wrote
+    pub fn get_signal(&self) -> Option<Value> {
+        self.signal
+    }
+
+    /// Feed signal into the first computer and run until every computer finished.
+    /// Returns the last value the last computer wrote
+    pub fn run(&mut self, signal: Value) -> Result<Value, AmplifierError> {
+        let count = self.computers.len();
+        if count == 0 {
+            return Err(AmplifierError::NoSignal);
+        }
+        self.computers[0].push_input(signal);
+
+        loop {
+            let mut progress = false;
+
+            for index in 0..count {
+                self.computers[index]
+                    .run_until_end()
+                    .map_err(|error| AmplifierError::Computer { index, error })?;
+
+                let output = self.computers[index].take_output();
+                progress |= !output.is_empty();
+
+                if index + 1 < count {
+                    self.computers[index + 1].extend_input(output);
+                } else {
+                    if let Some(signal) = output.last() {
+                        self.signal = Some(*signal);
+                    }
+                    if self.feedback {
+                        self.computers[0].extend_input(output);
+                    }
+                }
+            }
+
+            let finished = self
+                .computers
+                .iter()
+                .all(|computer| computer.get_status() == ComputerState::Finished);
+            if finished {
+                return self.signal.ok_or(AmplifierError::NoSignal);
+            }
+            // Without new output no waiting computer can continue
+            if !progress {
+                return Err(AmplifierError::Deadlock);
+            }
+        }
+    }
+}
+
+/// Try every permutation of phases on a chain of program and return the highest signal
+/// together with the phase setting producing it. Signal 0 is fed into the first amplifier
+pub fn max_signal(
+    level: Level,
+    program: &[Value],
+    phases: &[Value],
+    feedback: bool,
+) -> Result<Option<(Vec<Value>, Value)>, AmplifierError> {
+    let mut best: Option<(Vec<Value>, Value)> = None;
+
+    for permutation in permutations(phases) {
+        let mut chain =
+            AmplifierChain::with_phases(level, program.to_vec(), &permutation, feedback);
+        let signal = chain.run(0)?;
+        let better = match &best {
+            Some((_, max)) => signal > *max,
+            None => true,
+        };
+        if better {
+            best = Some((permutation, signal));
+        }
+    }
+
+    Ok(best)
+}
+
+/// All orderings of values (Heap's algorithm)
+fn permutations(values: &[Value]) -> Vec<Vec<Value>> {
+    let mut values = values.to_vec();
+    let mut result = vec![values.clone()];
+    let mut counters = vec![0; values.len()];
+    let mut index = 1;
+
+    while index < values.len() {
+        if counters[index] < index {
+            let other = if index % 2 == 0 { 0 } else { counters[index] };
+            values.swap(other, index);
+            result.push(values.clone());
+            counters[index] += 1;
+            index = 1;
+        } else {
+            counters[index] = 0;
+            index += 1;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::ErrorReason;
+
+    #[test]
+    fn amplifier_permutations() {
+        let mut result = permutations(&[0, 1, 2]);
+        result.sort();
+
+        assert_eq!(
+            result,
+            vec![
+                vec![0, 1, 2],
+                vec![0, 2, 1],
+                vec![1, 0, 2],
+                vec![1, 2, 0],
+                vec![2, 0, 1],
+                vec![2, 1, 0]
+            ]
+        );
+        assert_eq!(permutations(&[]), vec![Vec::<Value>::new()]);
+    }
+
+    #[test]
+    fn amplifier_chain() {
+        let program = [
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let mut chain =
+            AmplifierChain::with_phases(Level::Day05, program.to_vec(), &[4, 3, 2, 1, 0], false);
+
+        assert_eq!(chain.run(0), Ok(43210));
+        assert_eq!(
+            max_signal(Level::Day05, &program, &[0, 1, 2, 3, 4], false),
+            Ok(Some((vec![4, 3, 2, 1, 0], 43210)))
+        );
+    }
+
+    #[test]
+    fn amplifier_feedback() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+
+        assert_eq!(
+            max_signal(Level::Day05, &program, &[5, 6, 7, 8, 9], true),
+            Ok(Some((vec![9, 8, 7, 6, 5], 139629729)))
+        );
+    }
+
+    #[test]
+    fn amplifier_errors() {
+        // Second amplifier waits for a value the first never writes
+        let mut chain = AmplifierChain::new(
+            vec![
+                create_computer(Level::Day05, vec![3, 0, 3, 0, 99]),
+                create_computer(Level::Day05, vec![3, 0, 99]),
+            ],
+            false,
+        );
+        assert_eq!(chain.run(1), Err(AmplifierError::Deadlock));
+
+        let mut chain = AmplifierChain::new(
+            vec![
+                create_computer(Level::Day05, vec![3, 0, 4, 0, 99]),
+                create_computer(Level::Day05, vec![3, 0, 42]),
+            ],
+            false,
+        );
+        assert_eq!(
+            chain.run(1),
+            Err(AmplifierError::Computer {
+                index: 1,
+                error: IntcodeError::new(2, Some(42), ErrorReason::UnknownOpcode(42))
+            })
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use crate::intcode::instruction::ParameterMode;
+use crate::intcode::{create_empty_computer, Code, Computer, Level, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum AssembleError {
+    #[error("Line {line}: Unknown mnemonic {mnemonic}")]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[error("Line {line}: Expected {expected} operands, found {found}")]
+    OperandCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Line {line}: Invalid operand {operand}")]
+    InvalidOperand { line: usize, operand: String },
+    #[error("Line {line}: Invalid label {label}")]
+    InvalidLabel { line: usize, label: String },
+    #[error("Line {line}: Label {label} is already defined")]
+    DuplicateLabel { line: usize, label: String },
+    #[error("Line {line}: Unknown label {label}")]
+    UnknownLabel { line: usize, label: String },
+    #[error("Line {line}: Mode of operand {operand} is not supported at this level")]
+    UnsupportedMode { line: usize, operand: String },
+    #[error("Line {line}: Operand {operand} does not fit into a value")]
+    Overflow { line: usize, operand: String },
+}
+
+/// Number or label with optional offset
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Term {
+    Number(Value),
+    Label(String, Value),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Operand {
+    mode: ParameterMode,
+    term: Term,
+    text: String,
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Instruction { code: Code, operands: Vec<Operand> },
+    Data(Vec<Operand>),
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Instruction { operands, .. } => operands.len() + 1,
+            Item::Data(values) => values.len(),
+        }
+    }
+}
+
+/// Assemble source into a memory image with the instruction set of level
+///
+/// ```text
+/// ; comments start with a semicolon
+/// start:  in counter          ; position operand (label or number)
+/// loop:   add counter, #-1, counter
+///         out @0              ; '#' immediate, '@' relative
+///         jnz counter, #loop
+///         halt
+/// counter: data 0             ; data takes a comma separated list of values without modes
+/// ```
+///
+/// Labels can be used wherever a number is expected and may be offset as in `label+2`.
+pub fn assemble(level: Level, source: &str) -> Result<Vec<Value>, AssembleError> {
+    assemble_with(&create_empty_computer(level), source)
+}
+
+/// Assemble source into a memory image with the instructions registered on computer
+pub fn assemble_with(computer: &Computer, source: &str) -> Result<Vec<Value>, AssembleError> {
+    let instruction_set = computer.get_instruction_set();
+    let mnemonics = instruction_set
+        .codes()
+        .into_iter()
+        .filter_map(|code| Some((instruction_set.mnemonic(code)?, code)))
+        .collect::<HashMap<_, _>>();
+
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+
+        if let Some(position) = text.find(':') {
+            let label = text[..position].trim();
+            if !is_label(label) {
+                return Err(AssembleError::InvalidLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+            text = text[position + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(position) => (&text[..position], text[position..].trim()),
+            None => (text, ""),
+        };
+        let operands = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',')
+                .map(|operand| parse_operand(line, operand.trim()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let item = if mnemonic == "data" {
+            if let Some(operand) = operands
+                .iter()
+                .find(|operand| operand.mode != ParameterMode::Position)
+            {
+                return Err(AssembleError::InvalidOperand {
+                    line,
+                    operand: operand.text.clone(),
+                });
+            }
+            Item::Data(operands)
+        } else {
+            parse_instruction(computer, &mnemonics, line, mnemonic, operands)?
+        };
+        address += item.len();
+        items.push((line, item));
+    }
+
+    let mut memory = Vec::with_capacity(address);
+    for (line, item) in items {
+        match item {
+            Item::Instruction { code, operands } => {
+                let modes = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, operand)| mode_digit(operand.mode) * 10usize.pow(index as u32))
+                    .sum::<usize>();
+                memory.push((code + modes * 100) as Value);
+                for operand in &operands {
+                    memory.push(resolve(&labels, line, operand)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in &values {
+                    memory.push(resolve(&labels, line, value)?);
+                }
+            }
+        }
+    }
+
+    Ok(memory)
+}
+
+fn parse_instruction(
+    computer: &Computer,
+    mnemonics: &HashMap<&str, Code>,
+    line: usize,
+    mnemonic: &str,
+    operands: Vec<Operand>,
+) -> Result<Item, AssembleError> {
+    let code = *mnemonics
+        .get(mnemonic)
+        .ok_or_else(|| AssembleError::UnknownMnemonic {
+            line,
+            mnemonic: mnemonic.to_string(),
+        })?;
+    let arity = computer.get_instruction_set().arity(code).unwrap_or(0);
+
+    if operands.len() != arity {
+        return Err(AssembleError::OperandCount {
+            line,
+            expected: arity,
+            found: operands.len(),
+        });
+    }
+
+    let supported = |mode| match mode {
+        ParameterMode::Position => true,
+        ParameterMode::Immediate => computer.get_level() >= Level::ParameterModes,
+        ParameterMode::Relative => computer.get_level() >= Level::Day09,
+    };
+    if let Some(operand) = operands.iter().find(|operand| !supported(operand.mode)) {
+        return Err(AssembleError::UnsupportedMode {
+            line,
+            operand: operand.text.clone(),
+        });
+    }
+
+    Ok(Item::Instruction { code, operands })
+}
+
+fn parse_operand(line: usize, text: &str) -> Result<Operand, AssembleError> {
+    let invalid = || AssembleError::InvalidOperand {
+        line,
+        operand: text.to_string(),
+    };
+
+    let (mode, term) = if let Some(term) = text.strip_prefix('#') {
+        (ParameterMode::Immediate, term)
+    } else if let Some(term) = text.strip_prefix('@') {
+        (ParameterMode::Relative, term)
+    } else {
+        (ParameterMode::Position, text)
+    };
+    let term = term.trim();
+
+    let term = if let Ok(value) = term.parse::<Value>() {
+        Term::Number(value)
+    } else {
+        let (label, offset) = match term.find(['+', '-']) {
+            Some(position) => {
+                let offset = term[position..]
+                    .replace(' ', "")
+                    .trim_start_matches('+')
+                    .parse::<Value>()
+                    .map_err(|_| invalid())?;
+                (term[..position].trim(), offset)
+            }
+            None => (term, 0),
+        };
+        if !is_label(label) {
+            return Err(invalid());
+        }
+        Term::Label(label.to_string(), offset)
+    };
+
+    Ok(Operand {
+        mode,
+        term,
+        text: text.to_string(),
+    })
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn mode_digit(mode: ParameterMode) -> usize {
+    match mode {
+        ParameterMode::Position => 0,
+        ParameterMode::Immediate => 1,
+        ParameterMode::Relative => 2,
+    }
+}
+
+fn resolve(
+    labels: &HashMap<String, usize>,
+    line: usize,
+    operand: &Operand,
+) -> Result<Value, AssembleError> {
+    match &operand.term {
+        Term::Number(value) => Ok(*value),
+        Term::Label(label, offset) => {
+            let address = labels
+                .get(label)
+                .ok_or_else(|| AssembleError::UnknownLabel {
+                    line,
+                    label: label.clone(),
+                })?;
+            (*address as Value)
+                .checked_add(*offset)
+                .ok_or_else(|| AssembleError::Overflow {
+                    line,
+                    operand: operand.text.clone(),
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::disassembler::disassemble;
+    use crate::intcode::{create_computer, ComputerState};
+
+    #[test]
+    fn assemble_day02() {
+        let memory = assemble(
+            Level::Day02,
+            "add a, b, 3\nmul 3, c, 0\nhalt\na: data 30\nb: data 40\nc: data 50",
+        );
+
+        assert_eq!(memory, Ok(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]));
+    }
+
+    #[test]
+    fn assemble_and_run() {
+        let source = "
+            ; count down from input to 1
+                    in counter
+            loop:   out counter
+                    add counter, #-1, counter
+                    jnz counter, #loop
+                    halt
+            counter: data 0
+        ";
+        let memory = assemble(Level::Day05, source).expect("Failed assembling");
+        let mut c = create_computer(Level::Day05, memory);
+        c.push_input(3);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[3, 2, 1]);
+    }
+
+    #[test]
+    fn assemble_label_offset_and_relative() {
+        let memory = assemble(
+            Level::Day09,
+            "arb #values+1\nout @0\nhalt\nvalues: data 7, 8",
+        );
+
+        assert_eq!(memory, Ok(vec![109, 6, 204, 0, 99, 7, 8]));
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert_eq!(
+            assemble(Level::Day05, "halt\nfoo 1"),
+            Err(AssembleError::UnknownMnemonic {
+                line: 2,
+                mnemonic: "foo".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(Level::Day05, "add 1, 2"),
+            Err(AssembleError::OperandCount {
+                line: 1,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            assemble(Level::Day05, "jz #0, #end"),
+            Err(AssembleError::UnknownLabel {
+                line: 1,
+                label: "end".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(Level::Day05, "out @1"),
+            Err(AssembleError::UnsupportedMode {
+                line: 1,
+                operand: "@1".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(Level::Day05, "a: halt\na: halt"),
+            Err(AssembleError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(Level::Day05, "data 5, #5, @6"),
+            Err(AssembleError::InvalidOperand {
+                line: 1,
+                operand: "#5".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(Level::Day05, "halt\nx: data x+9223372036854775807"),
+            Err(AssembleError::Overflow {
+                line: 2,
+                operand: "x+9223372036854775807".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn assemble_disassemble_round_trip() {
+        let memory = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let source = disassemble(Level::Day05, &memory).to_source();
+
+        assert_eq!(assemble(Level::Day05, &source), Ok(memory));
+    }
+}
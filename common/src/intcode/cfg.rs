@@ -0,0 +1,693 @@
+use crate::intcode::disassembler::{decode_instruction, standard_code, Line, LineContent, Operand};
+use crate::intcode::instruction::ParameterMode;
+use crate::intcode::{create_empty_computer, Computer, Level, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+/// How control leaves a basic block. Targets are None if they are not statically known
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Exit {
+    /// Continues with the instruction at address
+    Next(usize),
+    /// Conditional jump to target, continues with next if not taken
+    Branch {
+        target: Option<usize>,
+        next: usize,
+    },
+    /// Jump that is always taken, like `jnz #1, #target`
+    Jump(Option<usize>),
+    /// Jump right after storing next on the relative base, like a compiled function call.
+    /// Control is expected to return to next
+    Call {
+        target: Option<usize>,
+        next: usize,
+    },
+    Halt,
+}
+
+impl Exit {
+    /// Statically known addresses control can continue at
+    pub fn successors(self) -> Vec<usize> {
+        match self {
+            Exit::Next(next) => vec![next],
+            Exit::Branch { target, next } => target.into_iter().chain(Some(next)).collect(),
+            Exit::Jump(target) => target.into_iter().collect(),
+            Exit::Call { target, next } => target.into_iter().chain(Some(next)).collect(),
+            Exit::Halt => Vec::new(),
+        }
+    }
+
+    /// Whether control can continue at an address that is not statically known
+    pub fn is_dynamic(self) -> bool {
+        matches!(
+            self,
+            Exit::Branch { target: None, .. } | Exit::Jump(None) | Exit::Call { target: None, .. }
+        )
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = |target: Option<usize>| target.map_or("?".to_string(), |t| t.to_string());
+        match self {
+            Exit::Next(next) => write!(f, "next {}", next),
+            Exit::Branch { target: t, next } => write!(f, "branch {} else {}", target(*t), next),
+            Exit::Jump(t) => write!(f, "jump {}", target(*t)),
+            Exit::Call { target: t, next } => write!(f, "call {} return {}", target(*t), next),
+            Exit::Halt => write!(f, "halt"),
+        }
+    }
+}
+
+/// Straight sequence of instructions that is only entered at its first one
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Block {
+    lines: Vec<Line>,
+    exit: Exit,
+}
+
+impl Block {
+    pub fn get_start(&self) -> usize {
+        self.lines[0].get_address()
+    }
+
+    /// Address after the last word of the block
+    pub fn get_end(&self) -> usize {
+        let last = &self.lines[self.lines.len() - 1];
+        last.get_address() + last.get_raw().len()
+    }
+
+    pub fn get_lines(&self) -> &Vec<Line> {
+        &self.lines
+    }
+
+    pub fn get_exit(&self) -> Exit {
+        self.exit
+    }
+}
+
+/// Instruction that writes to a word of reachable code
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct CodeWrite {
+    address: usize,
+    target: usize,
+}
+
+impl CodeWrite {
+    /// Address of the writing instruction
+    pub fn get_address(self) -> usize {
+        self.address
+    }
+
+    /// Address of the code word that is written
+    pub fn get_target(self) -> usize {
+        self.target
+    }
+}
+
+/// Control flow graph of a program, explored from address 0
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Cfg {
+    blocks: Vec<Block>,
+    data: Vec<Range<usize>>,
+    invalid: Vec<usize>,
+    code_writes: Vec<CodeWrite>,
+}
+
+impl Cfg {
+    /// Basic blocks ordered by start address
+    pub fn get_blocks(&self) -> &Vec<Block> {
+        &self.blocks
+    }
+
+    /// Block starting at address
+    pub fn get_block(&self, address: usize) -> Option<&Block> {
+        self.blocks
+            .binary_search_by_key(&address, Block::get_start)
+            .ok()
+            .map(|index| &self.blocks[index])
+    }
+
+    /// Ranges of the image that are not part of any reachable instruction
+    pub fn get_data(&self) -> &Vec<Range<usize>> {
+        &self.data
+    }
+
+    /// Addresses control can reach that do not hold a complete instruction
+    pub fn get_invalid(&self) -> &Vec<usize> {
+        &self.invalid
+    }
+
+    /// Writes into reachable code. Only writes with position mode addresses are detected
+    pub fn get_code_writes(&self) -> &Vec<CodeWrite> {
+        &self.code_writes
+    }
+
+    /// Graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+
+        for block in &self.blocks {
+            let label = block
+                .lines
+                .iter()
+                .map(|line| format!("{}: {}\\l", line.get_address(), line.get_content()))
+                .collect::<String>();
+            dot += &format!("    b{} [label=\"{}\"];\n", block.get_start(), label);
+        }
+        for address in &self.invalid {
+            dot += &format!(
+                "    b{0} [label=\"invalid {0}\", shape=octagon];\n",
+                address
+            );
+        }
+
+        for block in &self.blocks {
+            let start = block.get_start();
+            match block.exit {
+                Exit::Branch { target, next } => {
+                    if let Some(target) = target {
+                        dot += &format!("    b{} -> b{} [label=\"taken\"];\n", start, target);
+                    }
+                    dot += &format!("    b{} -> b{};\n", start, next);
+                }
+                Exit::Call { target, next } => {
+                    if let Some(target) = target {
+                        dot += &format!("    b{} -> b{} [label=\"call\"];\n", start, target);
+                    }
+                    dot += &format!("    b{} -> b{} [style=dotted];\n", start, next);
+                }
+                exit => {
+                    for successor in exit.successors() {
+                        dot += &format!("    b{} -> b{};\n", start, successor);
+                    }
+                }
+            }
+            if block.exit.is_dynamic() {
+                dot += &format!("    d{} [label=\"?\", shape=diamond];\n", start);
+                dot += &format!("    b{0} -> d{0} [style=dashed];\n", start);
+            }
+        }
+
+        dot += "}\n";
+        dot
+    }
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for block in &self.blocks {
+            writeln!(f, "block {}..{}:", block.get_start(), block.get_end())?;
+            for line in &block.lines {
+                writeln!(f, "{}", line)?;
+            }
+            writeln!(f, "       -> {}", block.exit)?;
+        }
+        for range in &self.data {
+            writeln!(f, "data {}..{}", range.start, range.end)?;
+        }
+        for address in &self.invalid {
+            writeln!(f, "invalid {}", address)?;
+        }
+        for write in &self.code_writes {
+            writeln!(f, "code write at {} to {}", write.address, write.target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Effect of a single instruction on control flow
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+enum Flow {
+    Next,
+    Halt,
+    /// Jump that is taken if its condition is not zero, or zero when jump_if_zero is set
+    Branch {
+        jump_if_zero: bool,
+    },
+}
+
+impl Flow {
+    /// Flow and index of the written parameter of line. Only built-in instructions that are not
+    /// overridden jump, halt or have a known write target
+    fn of(computer: &Computer, line: &Line) -> (Self, Option<usize>) {
+        match standard_code(computer, line) {
+            Some(1) | Some(2) | Some(7) | Some(8) => (Flow::Next, Some(2)),
+            Some(3) => (Flow::Next, Some(0)),
+            Some(5) => (
+                Flow::Branch {
+                    jump_if_zero: false,
+                },
+                None,
+            ),
+            Some(6) => (Flow::Branch { jump_if_zero: true }, None),
+            Some(99) => (Flow::Halt, None),
+            _ => (Flow::Next, None),
+        }
+    }
+}
+
+/// Build the control flow graph of memory with the instruction set of level
+pub fn analyze(level: Level, memory: &[Value]) -> Cfg {
+    analyze_with(&create_empty_computer(level), memory)
+}
+
+/// Build the control flow graph of memory with the instructions registered on computer.
+///
+/// Jump targets are known if they are immediate, or in position mode and read from a word that
+/// no reachable instruction writes to. Instructions that are not built in, or override a built-in
+/// one, never jump.
+/// A jump right after an add or mul of two immediates into a relative cell that yields the address
+/// after the jump is a call, and its return address is explored as well
+pub fn analyze_with(computer: &Computer, memory: &[Value]) -> Cfg {
+    // Position mode jumps whose target word is written, which grows until the graph is stable
+    let mut written_targets = BTreeSet::new();
+    loop {
+        let instructions = explore(computer, memory, &written_targets);
+        let writes = instructions
+            .values()
+            .filter_map(|(line, _)| write_address(computer, line))
+            .collect::<BTreeSet<_>>();
+        let before = written_targets.len();
+        written_targets.extend(instructions.iter().filter_map(|(address, (line, flow))| {
+            match (flow, operand(line, 1)) {
+                (Flow::Branch { .. }, Some(target))
+                    if target.get_mode() == ParameterMode::Position
+                        && writes.contains(&address_of(target.get_value())?) =>
+                {
+                    Some(*address)
+                }
+                _ => None,
+            }
+        }));
+        if written_targets.len() == before {
+            return build(computer, memory, instructions, &written_targets);
+        }
+    }
+}
+
+type Instructions = BTreeMap<usize, (Line, Flow)>;
+
+/// Decode every instruction reachable from address 0
+fn explore(
+    computer: &Computer,
+    memory: &[Value],
+    written_targets: &BTreeSet<usize>,
+) -> Instructions {
+    let mut instructions = Instructions::new();
+    let mut pending = vec![0];
+
+    while let Some(address) = pending.pop() {
+        if address >= memory.len() || instructions.contains_key(&address) {
+            continue;
+        }
+        let line = match decode_instruction(computer, memory, address, 0) {
+            Some(line) => line,
+            None => continue,
+        };
+        if let LineContent::Data(_) = line.get_content() {
+            continue;
+        }
+        let flow = Flow::of(computer, &line).0;
+        let exit = exit_of(computer, address, &line, flow, memory, written_targets);
+        pending.extend(exit.successors());
+        instructions.insert(address, (line, flow));
+    }
+
+    instructions
+}
+
+fn exit_of(
+    computer: &Computer,
+    address: usize,
+    line: &Line,
+    flow: Flow,
+    memory: &[Value],
+    written_targets: &BTreeSet<usize>,
+) -> Exit {
+    let next = address + line.get_raw().len();
+    let jump_if_zero = match flow {
+        Flow::Next => return Exit::Next(next),
+        Flow::Halt => return Exit::Halt,
+        Flow::Branch { jump_if_zero } => jump_if_zero,
+    };
+
+    let (condition, target) = match (operand(line, 0), operand(line, 1)) {
+        (Some(condition), Some(target)) => (condition, target),
+        _ => return Exit::Next(next),
+    };
+    let target = match target.get_mode() {
+        ParameterMode::Immediate => address_of(target.get_value()),
+        ParameterMode::Position if !written_targets.contains(&address) => {
+            address_of(target.get_value())
+                .and_then(|cell| memory.get(cell))
+                .and_then(|value| address_of(*value))
+        }
+        _ => None,
+    };
+
+    if condition.get_mode() == ParameterMode::Immediate {
+        if (condition.get_value() == 0) != jump_if_zero {
+            Exit::Next(next)
+        } else if stores_return_address(computer, memory, address, next) {
+            Exit::Call { target, next }
+        } else {
+            Exit::Jump(target)
+        }
+    } else {
+        Exit::Branch { target, next }
+    }
+}
+
+/// Whether the instruction right before address stores next on the relative base
+fn stores_return_address(
+    computer: &Computer,
+    memory: &[Value],
+    address: usize,
+    next: usize,
+) -> bool {
+    let line = match address
+        .checked_sub(4)
+        .and_then(|start| decode_instruction(computer, memory, start, 0))
+    {
+        Some(line) => line,
+        None => return false,
+    };
+    let modes = (0..3)
+        .map(|index| operand(&line, index).map(Operand::get_mode))
+        .collect::<Vec<_>>();
+    if modes
+        != [
+            Some(ParameterMode::Immediate),
+            Some(ParameterMode::Immediate),
+            Some(ParameterMode::Relative),
+        ]
+    {
+        return false;
+    }
+
+    let (a, b) = (line.get_raw()[1], line.get_raw()[2]);
+    let value = match standard_code(computer, &line) {
+        Some(1) => a.checked_add(b),
+        Some(2) => a.checked_mul(b),
+        _ => None,
+    };
+    value.and_then(address_of) == Some(next)
+}
+
+fn build(
+    computer: &Computer,
+    memory: &[Value],
+    instructions: Instructions,
+    written_targets: &BTreeSet<usize>,
+) -> Cfg {
+    let exits = instructions
+        .iter()
+        .map(|(address, (line, flow))| {
+            let exit = exit_of(computer, *address, line, *flow, memory, written_targets);
+            (*address, exit)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for exit in exits.values() {
+        if let Exit::Next(_) = exit {
+            continue;
+        }
+        leaders.extend(exit.successors());
+    }
+
+    let mut invalid = BTreeSet::new();
+    let mut blocks = Vec::new();
+    for leader in leaders.iter().copied() {
+        let mut address = leader;
+        let mut lines = Vec::new();
+        let exit = loop {
+            let (line, _) = match instructions.get(&address) {
+                Some(instruction) => instruction,
+                None => {
+                    invalid.insert(address);
+                    break None;
+                }
+            };
+            lines.push(line.clone());
+            match exits[&address] {
+                Exit::Next(next) if !leaders.contains(&next) => address = next,
+                exit => break Some(exit),
+            }
+        };
+        if let Some(exit) = exit {
+            blocks.push(Block { lines, exit });
+        }
+    }
+    for block in &blocks {
+        for successor in block.exit.successors() {
+            if !instructions.contains_key(&successor) {
+                invalid.insert(successor);
+            }
+        }
+    }
+
+    let mut code = vec![false; memory.len()];
+    for (address, (line, _)) in &instructions {
+        for word in &mut code[*address..*address + line.get_raw().len()] {
+            *word = true;
+        }
+    }
+    let mut data = Vec::new();
+    let mut address = 0;
+    while address < code.len() {
+        let end = (address..code.len())
+            .find(|end| code[*end] != code[address])
+            .unwrap_or(code.len());
+        if !code[address] {
+            data.push(address..end);
+        }
+        address = end;
+    }
+
+    let code_writes = instructions
+        .iter()
+        .filter_map(|(address, (line, _))| {
+            let target = write_address(computer, line)?;
+            if code.get(target) == Some(&true) {
+                Some(CodeWrite {
+                    address: *address,
+                    target,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    Cfg {
+        blocks,
+        data,
+        invalid: invalid.into_iter().collect(),
+        code_writes,
+    }
+}
+
+fn operand(line: &Line, index: usize) -> Option<Operand> {
+    match line.get_content() {
+        LineContent::Instruction { operands, .. } => operands.get(index).copied(),
+        LineContent::Data(_) => None,
+    }
+}
+
+/// Address written by line if it is known statically
+fn write_address(computer: &Computer, line: &Line) -> Option<usize> {
+    let index = Flow::of(computer, line).1?;
+    let operand = operand(line, index)?;
+    if operand.get_mode() == ParameterMode::Position {
+        address_of(operand.get_value())
+    } else {
+        None
+    }
+}
+
+fn address_of(value: Value) -> Option<usize> {
+    usize::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::create_computer;
+    use crate::intcode::instruction::Instruction;
+
+    #[test]
+    fn cfg_straight_line() {
+        let cfg = analyze(Level::Day02, &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+
+        assert_eq!(cfg.get_blocks().len(), 1);
+        let block = &cfg.get_blocks()[0];
+        assert_eq!((block.get_start(), block.get_end()), (0, 9));
+        assert_eq!(block.get_exit(), Exit::Halt);
+        assert_eq!(cfg.get_data().len(), 1);
+        assert_eq!(cfg.get_data()[0], 9..12);
+        assert!(cfg.get_invalid().is_empty());
+        // add writes into the operand of mul, mul into the opcode of add
+        let writes = cfg
+            .get_code_writes()
+            .iter()
+            .map(|write| (write.get_address(), write.get_target()))
+            .collect::<Vec<_>>();
+        assert_eq!(writes, vec![(0, 3), (4, 0)]);
+    }
+
+    #[test]
+    fn cfg_branches() {
+        let source = "
+                    in value
+                    jz value, #zero
+                    out #1
+                    jnz #1, #end
+            zero:   out #0
+            end:    halt
+            value:  data 0
+        ";
+        let memory = assemble(Level::Day05, source).expect("Failed assembling");
+        let cfg = analyze(Level::Day05, &memory);
+
+        let starts = cfg
+            .get_blocks()
+            .iter()
+            .map(Block::get_start)
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![0, 5, 10, 12]);
+        assert_eq!(
+            cfg.get_block(0).unwrap().get_exit(),
+            Exit::Branch {
+                target: Some(10),
+                next: 5
+            }
+        );
+        assert_eq!(cfg.get_block(5).unwrap().get_exit(), Exit::Jump(Some(12)));
+        assert_eq!(cfg.get_block(10).unwrap().get_exit(), Exit::Next(12));
+        assert_eq!(cfg.get_data().len(), 1);
+        assert_eq!(cfg.get_data()[0], 13..14);
+        assert!(cfg.get_code_writes().is_empty());
+
+        let text = cfg.to_string();
+        assert!(text.starts_with("block 0..5:\n"));
+        assert!(text.contains("       -> branch 10 else 5\n"));
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    b0 -> b10 [label=\"taken\"];\n"));
+        assert!(dot.contains("    b5 -> b12;\n"));
+        assert!(dot.contains("    b10 -> b12;\n"));
+    }
+
+    #[test]
+    fn cfg_dynamic_targets() {
+        // Jump through a constant word, a word that is written and a relative return address
+        let memory = vec![
+            1105, 1, 14, // jnz #1, #14
+            99, // unreachable halt
+            6, 20, 21, // jz 20, 21: target word is written below
+            1101, 0, 0, 21, // add #0, #0, 21
+            2105, 1, -1, // jnz #1, @-1
+            5, 20, 22, // jnz 20, 22: target read from word 22
+            1105, 1, 4, // jnz #1, #4
+            0, 3, 7,
+        ];
+        let cfg = analyze(Level::Day09, &memory);
+
+        assert_eq!(
+            cfg.get_block(14).unwrap().get_exit(),
+            Exit::Branch {
+                target: Some(7),
+                next: 17
+            }
+        );
+        assert_eq!(
+            cfg.get_block(4).unwrap().get_exit(),
+            Exit::Branch {
+                target: None,
+                next: 7
+            }
+        );
+        assert_eq!(cfg.get_block(7).unwrap().get_exit(), Exit::Jump(None));
+        assert!(cfg.get_block(7).unwrap().get_exit().is_dynamic());
+        assert_eq!(cfg.get_data(), &vec![3..4, 20..23]);
+        assert!(cfg.to_dot().contains("    b7 -> d7 [style=dashed];\n"));
+    }
+
+    #[test]
+    fn cfg_calls() {
+        let source = "
+                    arb #stack
+                    add #back, #0, @0
+                    jnz #1, #double
+            back:   halt
+            double: jz #0, @0
+            stack:  data 0
+        ";
+        let memory = assemble(Level::Day09, source).expect("Failed assembling");
+        let cfg = analyze(Level::Day09, &memory);
+
+        assert_eq!(
+            cfg.get_block(0).unwrap().get_exit(),
+            Exit::Call {
+                target: Some(10),
+                next: 9
+            }
+        );
+        assert_eq!(cfg.get_block(9).unwrap().get_exit(), Exit::Halt);
+        assert_eq!(cfg.get_block(10).unwrap().get_exit(), Exit::Jump(None));
+        assert!(cfg.to_string().contains("       -> call 10 return 9\n"));
+        assert!(cfg.to_dot().contains("    b0 -> b9 [style=dotted];\n"));
+    }
+
+    #[test]
+    fn cfg_invalid_and_self_modifying() {
+        // Patches its own halt into an output
+        let memory = vec![1101, 0, 4, 6, 104, 7, 99, 42];
+        let cfg = analyze(Level::Day05, &memory);
+
+        assert_eq!(cfg.get_blocks().len(), 1);
+        assert_eq!(cfg.get_blocks()[0].get_exit(), Exit::Halt);
+        assert_eq!(cfg.get_code_writes()[0].get_target(), 6);
+
+        let cfg = analyze(Level::Day05, &[1105, 1, 3, 42, 99]);
+        assert_eq!(cfg.get_invalid(), &vec![3]);
+        assert_eq!(cfg.get_data().len(), 1);
+        assert_eq!(cfg.get_data()[0], 3..5);
+        assert!(cfg
+            .to_dot()
+            .contains("b3 [label=\"invalid 3\", shape=octagon]"));
+    }
+
+    #[test]
+    fn cfg_overridden_instructions() {
+        // jz 0 jumps over the halt, unless jz is replaced by an instruction that never jumps
+        let memory = [1106, 0, 4, 99, 104, 1, 99];
+        let mut c = create_computer(Level::Day05, Vec::new());
+        assert_eq!(
+            analyze_with(&c, &memory).get_blocks()[0].get_exit(),
+            Exit::Jump(Some(4))
+        );
+
+        c.add_instruction(Instruction::new(6, "jz", 2, |c, _| {
+            c.increase_ptr(3);
+            Ok(())
+        }));
+        let cfg = analyze_with(&c, &memory);
+        assert_eq!(cfg.get_blocks().len(), 1);
+        assert_eq!(cfg.get_blocks()[0].get_exit(), Exit::Halt);
+
+        // An instruction named halt that is not the built-in one does not end the block
+        c.add_instruction(Instruction::new(50, "halt", 0, |c, _| {
+            c.increase_ptr(1);
+            Ok(())
+        }));
+        let cfg = analyze_with(&c, &[50, 104, 1, 99]);
+        assert_eq!(cfg.get_blocks()[0].get_end(), 4);
+        assert_eq!(cfg.get_blocks()[0].get_exit(), Exit::Halt);
+    }
+}
@@ -1,63 +1,224 @@
+use crate::intcode::computer::decoded::Op;
+use crate::intcode::computer::history::History;
 use crate::intcode::computer::ComputerState::Runnable;
-use crate::intcode::instruction::Instruction;
-use std::collections::HashMap;
+use crate::intcode::instruction::{Extend, Instruction, InstructionSet, Opcode, Parameters};
+use crate::intcode::io::{InputSource, OutputSink};
+use crate::intcode::snapshot::Snapshot;
+use crate::intcode::trace::{Observer, StepEvent};
+use crate::intcode::{create_empty_computer, ErrorReason, IntcodeError, Level, Value};
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::sync::Arc;
+use std::time::Instant;
 
+mod decoded;
+mod history;
+
+/// Memory and instructions are shared between clones and copied on the first write,
+/// so forking a running computer is cheap
 #[derive(Debug)]
 pub struct Computer {
-    main_memory: Vec<usize>,
-    extended_memory: Option<HashMap<usize, usize>>,
+    main_memory: Arc<Vec<Value>>,
+    extended_memory: Option<Arc<HashMap<usize, Value>>>,
     instruction_pointer: usize,
-    instructions: HashMap<usize, Instruction>,
+    relative_base: Value,
+    instruction_set: Arc<dyn InstructionSet>,
     state: ComputerState,
+    error: Option<IntcodeError>,
+    level: Level,
+    input: VecDeque<Value>,
+    output: Vec<Value>,
+    input_source: Option<Box<dyn InputSource>>,
+    output_sink: Option<Box<dyn OutputSink>>,
+    observer: Option<Box<dyn Observer>>,
+    /// Effects of the step that is traced for the observer
+    effects: Option<Effects>,
+    steps: u64,
+    /// Instructions decoded by run_decoded, by address in main memory
+    decoded: Arc<Vec<Option<Op>>>,
+    history: Option<History>,
+}
+
+/// The clone shares memory and instructions until either computer writes to them.
+/// Input source, output sink and observer are not cloned, the clone buffers its I/O instead
+impl Clone for Computer {
+    fn clone(&self) -> Self {
+        Self {
+            main_memory: Arc::clone(&self.main_memory),
+            extended_memory: self.extended_memory.clone(),
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base,
+            instruction_set: Arc::clone(&self.instruction_set),
+            state: self.state,
+            error: self.error.clone(),
+            level: self.level,
+            input: self.input.clone(),
+            output: self.output.clone(),
+            input_source: None,
+            output_sink: None,
+            observer: None,
+            effects: self.effects.clone(),
+            steps: self.steps,
+            decoded: Arc::clone(&self.decoded),
+            history: self.history.clone(),
+        }
+    }
+}
+
+/// Memory writes and I/O of a single step
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct Effects {
+    writes: Vec<(usize, Value)>,
+    input: Vec<Value>,
+    output: Vec<Value>,
 }
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum ComputerState {
     Runnable,
+    /// Paused on an input instruction without available input. Resumes on next step
+    WaitingForInput,
+    /// Execution failed. The cause is available through Computer::get_error
     Error,
     Finished,
+    /// Stopped by an exhausted step limit or deadline. Resumes on next step
+    Interrupted,
 }
 
+/// Steps between two checks of the clock in Computer::run_with_deadline
+const DEADLINE_CHECK_STEPS: u64 = 1024;
+
 impl Computer {
-    pub fn new(memory: Vec<usize>) -> Self {
+    /// Computer without any instructions
+    pub fn new(level: Level, memory: Vec<Value>) -> Self {
+        Self::with_instruction_set(level, memory, Vec::new())
+    }
+
+    pub fn new_with_instructions(
+        level: Level,
+        memory: Vec<Value>,
+        instructions: Vec<Instruction>,
+    ) -> Self {
+        Self::with_instruction_set(level, memory, instructions)
+    }
+
+    pub fn with_instruction_set<S: 'static + InstructionSet>(
+        level: Level,
+        memory: Vec<Value>,
+        instruction_set: S,
+    ) -> Self {
         Self {
-            main_memory: memory,
+            main_memory: Arc::new(memory),
             extended_memory: None,
             instruction_pointer: 0,
-            instructions: HashMap::new(),
+            relative_base: 0,
+            instruction_set: Arc::new(instruction_set),
             state: ComputerState::Runnable,
+            error: None,
+            level,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            input_source: None,
+            output_sink: None,
+            observer: None,
+            effects: None,
+            steps: 0,
+            decoded: Arc::new(Vec::new()),
+            history: None,
         }
     }
 
-    pub fn new_with_instructions(memory: Vec<usize>, instructions: Vec<Instruction>) -> Self {
-        let mut c = Self::new(memory);
-        for instruction in instructions {
-            c.instructions.insert(instruction.get_code(), instruction);
+    /// Computer with the state of snapshot and the instructions of its level
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut c = create_empty_computer(snapshot.level);
+        c.main_memory = Arc::new(snapshot.main_memory.clone());
+        if !snapshot.extended_memory.is_empty() {
+            c.extended_memory = Some(Arc::new(
+                snapshot.extended_memory.clone().into_iter().collect(),
+            ));
         }
+        c.instruction_pointer = snapshot.instruction_pointer;
+        c.relative_base = snapshot.relative_base;
+        c.steps = snapshot.steps;
+        c.state = snapshot.state;
+        c.input = snapshot.input.iter().copied().collect();
+        c.output = snapshot.output.clone();
+        c.error = snapshot.error.clone();
         c
     }
 
+    /// Capture memory, pointers, step count, state, error and I/O queues
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            level: self.level,
+            state: self.state,
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            main_memory: self.main_memory.to_vec(),
+            extended_memory: self
+                .extended_memory
+                .iter()
+                .flat_map(|ext| ext.iter())
+                .map(|(address, value)| (*address, *value))
+                .collect(),
+            input: self.input.iter().copied().collect(),
+            output: self.output.clone(),
+            error: self.error.clone(),
+        }
+    }
+
+    /// Add instruction on top of the instruction set, replacing an instruction with the same code
     pub fn add_instruction(&mut self, instruction: Instruction) {
-        self.instructions
-            .insert(instruction.get_code(), instruction);
+        let instruction_set = Arc::clone(&self.instruction_set);
+        self.set_instruction_set(Extend(instruction, instruction_set));
+    }
+
+    pub fn set_instruction_set<S: 'static + InstructionSet>(&mut self, instruction_set: S) {
+        self.instruction_set = Arc::new(instruction_set);
+        self.decoded = Arc::new(Vec::new());
+    }
+
+    pub fn get_instruction_set(&self) -> &Arc<dyn InstructionSet> {
+        &self.instruction_set
+    }
+
+    /// Decode raw value into opcode with the instruction set. None if the set does not know it
+    pub fn decode(&self, value: Value) -> Option<Opcode> {
+        self.instruction_set.decode(value)
     }
 
-    pub fn set_memory(&mut self, memory: Vec<usize>) {
-        self.main_memory = memory;
+    pub fn set_memory(&mut self, memory: Vec<Value>) {
+        self.main_memory = Arc::new(memory);
+        self.decoded = Arc::new(Vec::new());
         let _ = self.extended_memory.take();
         self.instruction_pointer = 0;
+        self.relative_base = 0;
         self.state = ComputerState::Runnable;
+        self.error = None;
+        self.steps = 0;
+        self.input.clear();
+        self.output.clear();
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
     }
 
-    pub fn get(&self, address: usize) -> Option<usize> {
-        if address > self.main_memory.len() - 1 {
+    /// Value at address. From Level::Day09 on, unset addresses past main memory read as 0
+    pub fn get(&self, address: usize) -> Option<Value> {
+        if address >= self.main_memory.len() {
+            let unset = if self.level >= Level::Day09 {
+                Some(0)
+            } else {
+                None
+            };
             match &self.extended_memory {
-                None => None,
+                None => unset,
                 Some(ext) => {
                     if ext.contains_key(&address) {
                         Some(ext[&address])
                     } else {
-                        None
+                        unset
                     }
                 }
             }
@@ -66,13 +227,30 @@ impl Computer {
         }
     }
 
-    pub fn set(&mut self, address: usize, value: usize) {
-        if address > self.main_memory.len() - 1 {
-            self.extended_memory
-                .get_or_insert(Default::default())
+    /// One past the highest address that holds a value, either in main memory or written past it
+    pub fn get_memory_end(&self) -> usize {
+        let extended = self
+            .extended_memory
+            .iter()
+            .flat_map(|ext| ext.keys())
+            .max()
+            .map_or(0, |address| address + 1);
+        self.main_memory.len().max(extended)
+    }
+
+    pub fn set(&mut self, address: usize, value: Value) {
+        if let Some(effects) = &mut self.effects {
+            effects.writes.push((address, value));
+        }
+        if self.history.is_some() {
+            self.record_write(address);
+        }
+        if address >= self.main_memory.len() {
+            Arc::make_mut(self.extended_memory.get_or_insert_with(Default::default))
                 .insert(address, value);
         } else {
-            self.main_memory[address] = value;
+            Arc::make_mut(&mut self.main_memory)[address] = value;
+            decoded::invalidate(&mut self.decoded, address);
         }
     }
 
@@ -84,29 +262,231 @@ impl Computer {
         self.state
     }
 
+    /// Error that put the computer into ComputerState::Error
+    pub fn get_error(&self) -> Option<&IntcodeError> {
+        self.error.as_ref()
+    }
+
+    /// Number of executed steps, including those waiting for input or failing
+    pub fn get_step_count(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn get_level(&self) -> Level {
+        self.level
+    }
+
+    pub fn push_input(&mut self, value: Value) {
+        self.input.push_back(value);
+    }
+
+    pub fn extend_input<I: IntoIterator<Item = Value>>(&mut self, values: I) {
+        self.input.extend(values);
+    }
+
+    /// Values pushed to the input queue that were not read yet
+    pub fn get_input(&self) -> &VecDeque<Value> {
+        &self.input
+    }
+
+    /// Next value of the input queue, or of the input source if the queue is empty
+    pub fn read_input(&mut self) -> Option<Value> {
+        let value = match self.input.pop_front() {
+            Some(value) => Some(value),
+            None => self.input_source.as_mut().and_then(|source| source.read()),
+        };
+        if let Some(value) = value {
+            if let Some(effects) = &mut self.effects {
+                effects.input.push(value);
+            }
+            self.record_input(value);
+        }
+        value
+    }
+
+    /// Write value to the output sink, or to the output buffer if there is none
+    pub fn write_output(&mut self, value: Value) {
+        if let Some(effects) = &mut self.effects {
+            effects.output.push(value);
+        }
+        match &mut self.output_sink {
+            Some(sink) => sink.write(value),
+            None => {
+                self.output.push(value);
+                self.record_output(value);
+            }
+        }
+    }
+
+    pub fn get_output(&self) -> &[Value] {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> Vec<Value> {
+        mem::take(&mut self.output)
+    }
+
+    pub fn set_input_source<S: 'static + InputSource>(&mut self, source: S) {
+        self.input_source = Some(Box::new(source));
+    }
+
+    pub fn set_output_sink<S: 'static + OutputSink>(&mut self, sink: S) {
+        self.output_sink = Some(Box::new(sink));
+    }
+
+    /// Install observer that is notified after every executed instruction
+    pub fn set_observer<O: 'static + Observer>(&mut self, observer: O) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    pub fn remove_observer(&mut self) {
+        self.observer = None;
+    }
+
     pub fn increase_ptr(&mut self, value: usize) {
         //TODO: Check validity before applying
         //eg. if larger than memory
         self.instruction_pointer += value;
     }
 
-    pub fn step(&mut self) -> ComputerState {
-        if self.state == ComputerState::Runnable {
-            let intcode = match self.get(self.instruction_pointer) {
-                Some(value) => value,
-                None => {
+    /// Move instruction pointer to absolute address. Fails if there is no memory at address
+    pub fn set_ptr(&mut self, address: usize) -> Result<(), ErrorReason> {
+        if self.get(address).is_some() {
+            self.instruction_pointer = address;
+            Ok(())
+        } else {
+            Err(ErrorReason::InvalidPointer(address))
+        }
+    }
+
+    pub fn get_ptr(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    pub fn get_relative_base(&self) -> Value {
+        self.relative_base
+    }
+
+    pub fn adjust_relative_base(&mut self, value: Value) -> Result<(), ErrorReason> {
+        self.relative_base = self
+            .relative_base
+            .checked_add(value)
+            .ok_or(ErrorReason::Overflow)?;
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<ComputerState, IntcodeError> {
+        match self.state {
+            ComputerState::Runnable
+            | ComputerState::WaitingForInput
+            | ComputerState::Interrupted => {
+                self.begin_record();
+                if self.state == ComputerState::Interrupted {
+                    self.state = ComputerState::Runnable;
+                }
+                self.steps += 1;
+                let address = self.instruction_pointer;
+                let intcode = self.get(address);
+                let event = if self.observer.is_some() {
+                    Some(self.begin_trace(address, intcode))
+                } else {
+                    None
+                };
+                let result = self.execute(intcode);
+                if let Err(reason) = result {
                     self.state = ComputerState::Error;
-                    return self.state;
+                    self.error = Some(IntcodeError::new(address, intcode, reason));
                 }
-            };
-            let instruction = self.instructions[&intcode].clone();
-            instruction.run_instruction(self, self.instruction_pointer);
+                if let Some(event) = event {
+                    self.end_trace(event, result.err());
+                }
+                self.end_record();
+                if let Some(error) = &self.error {
+                    return Err(error.clone());
+                }
+            }
+            ComputerState::Error => {
+                if let Some(error) = &self.error {
+                    return Err(error.clone());
+                }
+            }
+            ComputerState::Finished => {}
         }
-        self.state
+        Ok(self.state)
     }
 
-    pub fn run_until_end(&mut self) -> ComputerState {
-        while self.step() == Runnable {}
-        self.state
+    fn execute(&mut self, intcode: Option<Value>) -> Result<(), ErrorReason> {
+        let intcode = intcode.ok_or(ErrorReason::NoInstruction)?;
+        let opcode = self
+            .decode(intcode)
+            .ok_or(ErrorReason::UnknownOpcode(intcode))?;
+        let instruction_set = Arc::clone(&self.instruction_set);
+        instruction_set.execute(self, &Parameters::new(self.instruction_pointer, opcode))
+    }
+
+    fn begin_trace(&mut self, address: usize, intcode: Option<Value>) -> StepEvent {
+        let code = intcode
+            .and_then(|intcode| self.decode(intcode))
+            .map(Opcode::get_code);
+        let mnemonic = code.and_then(|code| self.instruction_set.mnemonic(code));
+        let arity = code
+            .and_then(|code| self.instruction_set.arity(code))
+            .unwrap_or(0);
+        let operands = (address + 1..=address + arity)
+            .map_while(|address| self.get(address))
+            .collect();
+
+        self.effects = Some(Effects::default());
+        StepEvent::new(address, intcode, mnemonic, operands)
+    }
+
+    fn end_trace(&mut self, mut event: StepEvent, error: Option<ErrorReason>) {
+        let effects = self.effects.take().unwrap_or_default();
+        event.finish(
+            effects.writes,
+            effects.input,
+            effects.output,
+            self.state,
+            error,
+        );
+        if let Some(observer) = &mut self.observer {
+            observer.on_step(&event);
+        }
+    }
+
+    pub fn run_until_end(&mut self) -> Result<ComputerState, IntcodeError> {
+        while self.step()? == Runnable {}
+        Ok(self.state)
+    }
+
+    /// Run at most max_steps steps. Returns ComputerState::Interrupted if the computer could continue
+    pub fn run_with_limit(&mut self, max_steps: u64) -> Result<ComputerState, IntcodeError> {
+        for _ in 0..max_steps {
+            if self.step()? != Runnable {
+                return Ok(self.state);
+            }
+        }
+        self.interrupt()
+    }
+
+    /// Run until deadline has passed. Returns ComputerState::Interrupted if the computer could continue.
+    /// The clock is only checked every few steps, so the deadline may be overrun slightly
+    pub fn run_with_deadline(&mut self, deadline: Instant) -> Result<ComputerState, IntcodeError> {
+        while Instant::now() < deadline {
+            match self.run_with_limit(DEADLINE_CHECK_STEPS)? {
+                ComputerState::Interrupted => {}
+                state => return Ok(state),
+            }
+        }
+        self.interrupt()
+    }
+
+    fn interrupt(&mut self) -> Result<ComputerState, IntcodeError> {
+        match self.state {
+            ComputerState::Runnable => self.state = ComputerState::Interrupted,
+            ComputerState::Error => return self.step(),
+            _ => {}
+        }
+        Ok(self.state)
     }
 }
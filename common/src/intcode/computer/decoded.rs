@@ -0,0 +1,351 @@
+use crate::intcode::instruction::{InstructionSet, ParameterMode};
+use crate::intcode::{to_address, Code, Computer, ComputerState, ErrorReason, IntcodeError, Value};
+use std::sync::Arc;
+
+/// Built-in instruction a decoded operation executes
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+enum Kind {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jnz,
+    Jz,
+    Lt,
+    Eq,
+    Arb,
+    Halt,
+}
+
+impl Kind {
+    /// Kind of instruction with code if the instruction set runs the built-in one for it
+    fn of(instruction_set: &dyn InstructionSet, code: Code) -> Option<Self> {
+        if !instruction_set.is_standard(code) {
+            return None;
+        }
+        let kind = match code {
+            1 => Kind::Add,
+            2 => Kind::Mul,
+            3 => Kind::In,
+            4 => Kind::Out,
+            5 => Kind::Jnz,
+            6 => Kind::Jz,
+            7 => Kind::Lt,
+            8 => Kind::Eq,
+            9 => Kind::Arb,
+            99 => Kind::Halt,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Kind::Add | Kind::Mul | Kind::Lt | Kind::Eq => 3,
+            Kind::Jnz | Kind::Jz => 2,
+            Kind::In | Kind::Out | Kind::Arb => 1,
+            Kind::Halt => 0,
+        }
+    }
+
+    /// Index of the parameter that is written to
+    fn write_index(self) -> Option<usize> {
+        match self {
+            Kind::Add | Kind::Mul | Kind::Lt | Kind::Eq => Some(2),
+            Kind::In => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Instruction decoded with its modes and raw parameters. Only valid until its words are written
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub(super) struct Op {
+    kind: Kind,
+    opcode: Value,
+    modes: [ParameterMode; 3],
+    params: [Value; 3],
+}
+
+/// Longest instruction that gets decoded, so a write can only affect operations that many words before
+const MAX_ARITY: usize = 3;
+
+/// Drop decoded operations that contain address
+pub(super) fn invalidate(decoded: &mut Arc<Vec<Option<Op>>>, address: usize) {
+    let start = address.saturating_sub(MAX_ARITY);
+    let end = decoded.len().min(address + 1);
+    if start < end && decoded[start..end].iter().any(Option::is_some) {
+        for op in &mut Arc::make_mut(decoded)[start..end] {
+            *op = None;
+        }
+    }
+}
+
+impl Computer {
+    /// Same as run_until_end, but built-in instructions are decoded once and cached.
+    /// Words that do not decode into a built-in instruction with valid modes run through step,
+    /// as does everything while an observer is installed or history is recorded
+    pub fn run_decoded(&mut self) -> Result<ComputerState, IntcodeError> {
+        if self.observer.is_some() || self.history.is_some() {
+            return self.run_until_end();
+        }
+
+        loop {
+            match self.state {
+                ComputerState::Runnable | ComputerState::WaitingForInput => {}
+                ComputerState::Interrupted => self.state = ComputerState::Runnable,
+                ComputerState::Error => return self.step(),
+                ComputerState::Finished => return Ok(self.state),
+            }
+
+            let address = self.instruction_pointer;
+            let op = match self.decoded_at(address) {
+                Some(op) => op,
+                None => {
+                    if self.step()? != ComputerState::Runnable {
+                        return Ok(self.state);
+                    }
+                    continue;
+                }
+            };
+
+            self.steps += 1;
+            if let Err(reason) = self.execute_op(op) {
+                self.state = ComputerState::Error;
+                let error = IntcodeError::new(address, Some(op.opcode), reason);
+                self.error = Some(error.clone());
+                return Err(error);
+            }
+            if self.state != ComputerState::Runnable {
+                return Ok(self.state);
+            }
+        }
+    }
+
+    fn decoded_at(&mut self, address: usize) -> Option<Op> {
+        if let Some(Some(op)) = self.decoded.get(address) {
+            return Some(*op);
+        }
+
+        let op = self.decode_op(address)?;
+        let len = self.main_memory.len();
+        let decoded = Arc::make_mut(&mut self.decoded);
+        if decoded.len() != len {
+            decoded.resize(len, None);
+        }
+        decoded[address] = Some(op);
+        Some(op)
+    }
+
+    fn decode_op(&self, address: usize) -> Option<Op> {
+        let opcode = *self.main_memory.get(address)?;
+        let decoded = self.decode(opcode)?;
+        let kind = Kind::of(self.instruction_set.as_ref(), decoded.get_code())?;
+
+        let mut modes = [ParameterMode::Position; 3];
+        let mut params = [0; 3];
+        for index in 0..kind.arity() {
+            modes[index] = decoded.get_mode_for_level(index, self.level)?;
+            params[index] = *self.main_memory.get(address + 1 + index)?;
+        }
+        if let Some(index) = kind.write_index() {
+            if modes[index] == ParameterMode::Immediate {
+                return None;
+            }
+        }
+
+        Some(Op {
+            kind,
+            opcode,
+            modes,
+            params,
+        })
+    }
+
+    fn execute_op(&mut self, op: Op) -> Result<(), ErrorReason> {
+        match op.kind {
+            Kind::Add => self.apply_op(op, |a, b| a.checked_add(b)),
+            Kind::Mul => self.apply_op(op, |a, b| a.checked_mul(b)),
+            Kind::Lt => self.apply_op(op, |a, b| Some((a < b) as Value)),
+            Kind::Eq => self.apply_op(op, |a, b| Some((a == b) as Value)),
+            Kind::In => {
+                let address = self.op_address(op, 0)?;
+                match self.read_input() {
+                    Some(value) => {
+                        self.set(address, value);
+                        self.state = ComputerState::Runnable;
+                        self.instruction_pointer += 2;
+                    }
+                    None => self.state = ComputerState::WaitingForInput,
+                }
+                Ok(())
+            }
+            Kind::Out => {
+                let value = self.op_value(op, 0)?;
+                self.write_output(value);
+                self.instruction_pointer += 2;
+                Ok(())
+            }
+            Kind::Jnz | Kind::Jz => {
+                if (self.op_value(op, 0)? != 0) == (op.kind == Kind::Jnz) {
+                    let target = self.op_value(op, 1)?;
+                    self.set_ptr(to_address(target)?)
+                } else {
+                    self.instruction_pointer += 3;
+                    Ok(())
+                }
+            }
+            Kind::Arb => {
+                let value = self.op_value(op, 0)?;
+                self.adjust_relative_base(value)?;
+                self.instruction_pointer += 2;
+                Ok(())
+            }
+            Kind::Halt => {
+                self.state = ComputerState::Finished;
+                Ok(())
+            }
+        }
+    }
+
+    fn apply_op<F: Fn(Value, Value) -> Option<Value>>(
+        &mut self,
+        op: Op,
+        todo: F,
+    ) -> Result<(), ErrorReason> {
+        let value1 = self.op_value(op, 0)?;
+        let value2 = self.op_value(op, 1)?;
+        let address = self.op_address(op, 2)?;
+
+        self.set(address, todo(value1, value2).ok_or(ErrorReason::Overflow)?);
+        self.instruction_pointer += 4;
+        Ok(())
+    }
+
+    fn op_value(&self, op: Op, index: usize) -> Result<Value, ErrorReason> {
+        if op.modes[index] == ParameterMode::Immediate {
+            return Ok(op.params[index]);
+        }
+        let address = self.op_address(op, index)?;
+        self.get(address)
+            .ok_or(ErrorReason::InvalidRead { address, index })
+    }
+
+    fn op_address(&self, op: Op, index: usize) -> Result<usize, ErrorReason> {
+        let address = match op.modes[index] {
+            ParameterMode::Relative => self
+                .relative_base
+                .checked_add(op.params[index])
+                .ok_or(ErrorReason::Overflow)?,
+            _ => op.params[index],
+        };
+        Ok(to_address(address)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::instruction::{default_instruction_appy, Instruction};
+    use crate::intcode::{create_computer, ComputerState, Level, Value};
+
+    /// Run memory with input on both paths and compare everything observable
+    fn compare(level: Level, memory: Vec<Value>, input: &[Value]) -> Vec<Value> {
+        let mut slow = create_computer(level, memory);
+        slow.extend_input(input.iter().copied());
+        let mut fast = slow.clone();
+
+        let result = slow.run_until_end();
+        assert_eq!(fast.run_decoded(), result);
+        assert_eq!(fast.snapshot(), slow.snapshot());
+        assert_eq!(fast.get_error(), slow.get_error());
+        assert_eq!(fast.get_step_count(), slow.get_step_count());
+        fast.take_output()
+    }
+
+    #[test]
+    fn decoded_examples() {
+        let day05 = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        assert_eq!(compare(Level::Day05, day05.clone(), &[7]), vec![999]);
+        assert_eq!(compare(Level::Day05, day05.clone(), &[8]), vec![1000]);
+        assert_eq!(compare(Level::Day05, day05, &[9]), vec![1001]);
+
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(compare(Level::Day09, quine.clone(), &[]), quine);
+
+        assert_eq!(
+            compare(Level::Day02, vec![1, 1, 1, 4, 99, 5, 6, 0, 99], &[]),
+            Vec::<Value>::new()
+        );
+    }
+
+    #[test]
+    fn decoded_self_modifying() {
+        let source = "
+            loop:   out #0
+                    add loop+1, #1, loop+1
+                    lt loop+1, #3, tmp
+                    jnz tmp, #loop
+                    halt
+            tmp:    data 0
+        ";
+        let memory = assemble(Level::Day05, source).expect("Failed assembling");
+
+        assert_eq!(compare(Level::Day05, memory, &[]), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn decoded_errors() {
+        // Unknown mode, immediate write address, negative address, overflow, invalid pointer
+        compare(Level::Day05, vec![1, 0, 0, 0, 204, 0, 99], &[]);
+        compare(Level::Day05, vec![11101, 1, 2, 3, 99], &[]);
+        compare(Level::Day05, vec![1, -1, 0, 0, 99], &[]);
+        compare(Level::Day05, vec![1102, Value::MAX, 2, 0, 99], &[]);
+        compare(Level::Day09, vec![109, Value::MAX, 109, 1, 99], &[]);
+        compare(Level::Day05, vec![1105, 1, 100, 99], &[]);
+        compare(Level::Day05, vec![1, 0, 0, 0, 42], &[]);
+        compare(Level::Day02, vec![1, 0, 0], &[]);
+    }
+
+    #[test]
+    fn decoded_overridden_instruction() {
+        // add replaced by a subtraction with the same code, mnemonic and arity
+        let mut c = create_computer(Level::Day05, vec![1101, 5, 3, 0, 4, 0, 99]);
+        c.add_instruction(Instruction::new(1, "add", 3, |c, p| {
+            default_instruction_appy(c, p, |a, b| a.checked_sub(b))
+        }));
+        let mut fast = c.clone();
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(fast.run_decoded(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[2]);
+        assert_eq!(fast.get_output(), &[2]);
+    }
+
+    #[test]
+    fn decoded_waiting_for_input() {
+        let mut c = create_computer(Level::Day05, vec![3, 9, 4, 9, 1105, 1, 0, 99, 0, 0]);
+
+        assert_eq!(c.run_decoded(), Ok(ComputerState::WaitingForInput));
+        c.push_input(5);
+        assert_eq!(c.run_decoded(), Ok(ComputerState::WaitingForInput));
+        c.extend_input(vec![6, 7]);
+        assert_eq!(c.run_decoded(), Ok(ComputerState::WaitingForInput));
+        assert_eq!(c.take_output(), vec![5, 6, 7]);
+
+        // A fork shares the decoded program, but not the memory written afterwards
+        let mut fork = c.clone();
+        fork.push_input(8);
+        c.push_input(9);
+        assert_eq!(fork.run_decoded(), Ok(ComputerState::WaitingForInput));
+        assert_eq!(c.run_decoded(), Ok(ComputerState::WaitingForInput));
+        assert_eq!(fork.get_output(), &[8]);
+        assert_eq!(c.get_output(), &[9]);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::intcode::{Computer, ComputerState, IntcodeError, Value};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Everything a single step changed, enough to undo it
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Record {
+    instruction_pointer: usize,
+    relative_base: Value,
+    state: ComputerState,
+    error: Option<IntcodeError>,
+    steps: u64,
+    /// Written addresses with their previous value, None if the address was unset
+    writes: Vec<(usize, Option<Value>)>,
+    input: Vec<Value>,
+    /// Values appended to the output buffer. Values written to an output sink cannot be undone
+    output: Vec<Value>,
+}
+
+/// Undo log of the most recent steps
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(super) struct History {
+    records: VecDeque<Record>,
+    capacity: usize,
+    current: Option<Record>,
+}
+
+impl History {
+    fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity,
+            current: None,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.records.clear();
+        self.current = None;
+    }
+}
+
+impl Computer {
+    /// Record an undo log of the last capacity steps, so execution can be reversed.
+    /// Changes made outside of a step, like Computer::set or pushed input, are not recorded
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Number of steps that can be undone
+    pub fn get_history_len(&self) -> usize {
+        self.history
+            .as_ref()
+            .map_or(0, |history| history.records.len())
+    }
+
+    /// Undo the last recorded step: restore memory, pointers and state, put consumed input back
+    /// in front of the input queue and remove output that is still buffered.
+    /// Returns false if there is no step to undo
+    pub fn step_back(&mut self) -> bool {
+        let record = match self
+            .history
+            .as_mut()
+            .and_then(|history| history.records.pop_back())
+        {
+            Some(record) => record,
+            None => return false,
+        };
+
+        for (address, value) in record.writes.into_iter().rev() {
+            self.restore(address, value);
+        }
+        for value in record.input.into_iter().rev() {
+            self.input.push_front(value);
+        }
+        if self.output.ends_with(&record.output) {
+            self.output
+                .truncate(self.output.len() - record.output.len());
+        }
+        self.instruction_pointer = record.instruction_pointer;
+        self.relative_base = record.relative_base;
+        self.state = record.state;
+        self.error = record.error;
+        self.steps = record.steps;
+        true
+    }
+
+    /// Step back until stop returns true for the computer. Returns false if the history ran out first
+    pub fn run_back_until<F: FnMut(&Computer) -> bool>(&mut self, mut stop: F) -> bool {
+        while self.step_back() {
+            if stop(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Step back to right before the last recorded step that wrote to address.
+    /// Returns false if no recorded step wrote to it
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        loop {
+            let wrote = match self
+                .history
+                .as_ref()
+                .and_then(|history| history.records.back())
+            {
+                Some(record) => record.writes.iter().any(|(written, _)| *written == address),
+                None => return false,
+            };
+            self.step_back();
+            if wrote {
+                return true;
+            }
+        }
+    }
+
+    pub(super) fn begin_record(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.current = Some(Record {
+                instruction_pointer: self.instruction_pointer,
+                relative_base: self.relative_base,
+                state: self.state,
+                error: self.error.clone(),
+                steps: self.steps,
+                writes: Vec::new(),
+                input: Vec::new(),
+                output: Vec::new(),
+            });
+        }
+    }
+
+    pub(super) fn end_record(&mut self) {
+        if let Some(history) = &mut self.history {
+            if let Some(record) = history.current.take() {
+                if history.records.len() >= history.capacity {
+                    history.records.pop_front();
+                }
+                if history.capacity > 0 {
+                    history.records.push_back(record);
+                }
+            }
+        }
+    }
+
+    pub(super) fn record_write(&mut self, address: usize) {
+        let old = if address < self.main_memory.len() {
+            Some(self.main_memory[address])
+        } else {
+            self.extended_memory
+                .as_ref()
+                .and_then(|ext| ext.get(&address).copied())
+        };
+        if let Some(record) = self.current_record() {
+            record.writes.push((address, old));
+        }
+    }
+
+    pub(super) fn record_input(&mut self, value: Value) {
+        if let Some(record) = self.current_record() {
+            record.input.push(value);
+        }
+    }
+
+    pub(super) fn record_output(&mut self, value: Value) {
+        if let Some(record) = self.current_record() {
+            record.output.push(value);
+        }
+    }
+
+    fn current_record(&mut self) -> Option<&mut Record> {
+        self.history
+            .as_mut()
+            .and_then(|history| history.current.as_mut())
+    }
+
+    fn restore(&mut self, address: usize, value: Option<Value>) {
+        match value {
+            Some(value) if address < self.main_memory.len() => {
+                Arc::make_mut(&mut self.main_memory)[address] = value;
+                super::decoded::invalidate(&mut self.decoded, address);
+            }
+            Some(value) => {
+                Arc::make_mut(self.extended_memory.get_or_insert_with(Default::default))
+                    .insert(address, value);
+            }
+            None => {
+                if let Some(ext) = &mut self.extended_memory {
+                    Arc::make_mut(ext).remove(&address);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::{create_computer, ComputerState, Level};
+
+    #[test]
+    fn step_back_restores_everything() {
+        // Reads two values, outputs their product and stores it past main memory
+        let memory = vec![3, 11, 3, 12, 2, 11, 12, 100, 4, 100, 99, 0, 0];
+        let mut c = create_computer(Level::Day09, memory);
+        c.enable_history(100);
+        c.extend_input(vec![6, 7]);
+        let start = c.snapshot();
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[42]);
+        assert_eq!(c.get_history_len(), 5);
+        let end = c.snapshot();
+
+        assert!(c.step_back());
+        assert_eq!(c.get_output(), &[42]);
+        assert!(c.step_back());
+        assert!(c.get_output().is_empty());
+        assert_eq!(c.get(100), Some(42));
+        assert!(c.step_back());
+        assert_eq!(c.get_ptr(), 4);
+        assert_eq!(c.get(100), Some(0));
+        assert!(c.step_back() && c.step_back());
+        assert!(!c.step_back());
+        assert_eq!(c.snapshot(), start);
+        assert_eq!(c.get_step_count(), 0);
+
+        // Running again reproduces the same end state
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.snapshot(), end);
+    }
+
+    #[test]
+    fn run_back_to_write() {
+        // Writes cell 0 twice: once in the first add, once in the mul
+        let mut c = create_computer(Level::Day02, vec![1, 0, 0, 0, 2, 0, 12, 0, 99, 0, 0, 0, 3]);
+        c.enable_history(10);
+        c.run_until_end().unwrap();
+        assert_eq!(c.get(0), Some(6));
+
+        assert!(c.run_back_to_write(0));
+        assert_eq!(c.get_ptr(), 4);
+        assert_eq!(c.get(0), Some(2));
+        assert!(c.run_back_to_write(0));
+        assert_eq!(c.get_ptr(), 0);
+        assert!(!c.run_back_to_write(0));
+    }
+
+    #[test]
+    fn run_back_until_and_capacity() {
+        let mut c = create_computer(Level::Day05, vec![1101, 0, 0, 9, 1101, 0, 0, 9, 99, 0]);
+        c.enable_history(2);
+        c.run_until_end().unwrap();
+        assert_eq!(c.get_history_len(), 2);
+
+        assert!(c.run_back_until(|c| c.get_ptr() == 4));
+        assert!(!c.run_back_until(|c| c.get_ptr() == 0));
+        assert_eq!(c.get_ptr(), 4);
+
+        // A step waiting for input is undone, input pushed afterwards stays in the queue
+        let mut c = create_computer(Level::Day05, vec![3, 0, 99]);
+        c.enable_history(10);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+        c.push_input(5);
+        c.run_until_end().unwrap();
+        assert_eq!(c.get(0), Some(5));
+        assert!(c.run_back_until(|c| c.get_status() == ComputerState::WaitingForInput));
+        assert_eq!(c.get(0), Some(3));
+        assert_eq!(c.get_input(), &[5]);
+    }
+}
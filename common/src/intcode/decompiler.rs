@@ -0,0 +1,803 @@
+use crate::intcode::cfg::{analyze_with, Block, Cfg, Exit};
+use crate::intcode::disassembler::{standard_code, Line, LineContent, Operand};
+use crate::intcode::instruction::ParameterMode;
+use crate::intcode::{create_empty_computer, Computer, Level, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+/// Comparison of two expressions, like `var_20 != 0`
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Condition {
+    left: String,
+    operator: &'static str,
+    right: String,
+}
+
+impl Condition {
+    pub fn new(left: String, operator: &'static str, right: String) -> Self {
+        Self {
+            left,
+            operator,
+            right,
+        }
+    }
+
+    pub fn negate(&self) -> Self {
+        let operator = match self.operator {
+            "==" => "!=",
+            "!=" => "==",
+            "<" => ">=",
+            _ => "<",
+        };
+        Self::new(self.left.clone(), operator, self.right.clone())
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.operator, self.right)
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum Statement {
+    /// Statement of a single instruction, like `var_20 = var_21 + 1`
+    Simple(String),
+    If {
+        condition: Condition,
+        then: Vec<Statement>,
+        otherwise: Vec<Statement>,
+    },
+    While {
+        condition: Condition,
+        body: Vec<Statement>,
+    },
+    DoWhile {
+        body: Vec<Statement>,
+        condition: Condition,
+    },
+    Loop(Vec<Statement>),
+    Label(usize),
+    Goto(usize),
+    /// Jump to an address that is not statically known
+    GotoDynamic(String),
+    Call(usize),
+    CallDynamic(String),
+    Return,
+    Break,
+    Continue,
+    Halt,
+}
+
+impl Statement {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = "    ".repeat(indent);
+        match self {
+            Statement::Simple(text) => writeln!(f, "{}{};", pad, text),
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                writeln!(f, "{}if ({}) {{", pad, condition)?;
+                write_body(f, then, indent + 1)?;
+                if !otherwise.is_empty() {
+                    writeln!(f, "{}}} else {{", pad)?;
+                    write_body(f, otherwise, indent + 1)?;
+                }
+                writeln!(f, "{}}}", pad)
+            }
+            Statement::While { condition, body } => {
+                writeln!(f, "{}while ({}) {{", pad, condition)?;
+                write_body(f, body, indent + 1)?;
+                writeln!(f, "{}}}", pad)
+            }
+            Statement::DoWhile { body, condition } => {
+                writeln!(f, "{}do {{", pad)?;
+                write_body(f, body, indent + 1)?;
+                writeln!(f, "{}}} while ({});", pad, condition)
+            }
+            Statement::Loop(body) => {
+                writeln!(f, "{}loop {{", pad)?;
+                write_body(f, body, indent + 1)?;
+                writeln!(f, "{}}}", pad)
+            }
+            Statement::Label(address) => {
+                writeln!(f, "{}L{}:", "    ".repeat(indent - 1), address)
+            }
+            Statement::Goto(address) => writeln!(f, "{}goto L{};", pad, address),
+            Statement::GotoDynamic(target) => writeln!(f, "{}goto *{};", pad, target),
+            Statement::Call(address) => writeln!(f, "{}f{}();", pad, address),
+            Statement::CallDynamic(target) => writeln!(f, "{}(*{})();", pad, target),
+            Statement::Return => writeln!(f, "{}return;", pad),
+            Statement::Break => writeln!(f, "{}break;", pad),
+            Statement::Continue => writeln!(f, "{}continue;", pad),
+            Statement::Halt => writeln!(f, "{}halt;", pad),
+        }
+    }
+}
+
+fn write_body(f: &mut fmt::Formatter<'_>, body: &[Statement], indent: usize) -> fmt::Result {
+    for statement in body {
+        statement.write(f, indent)?;
+    }
+    Ok(())
+}
+
+/// Code reachable from an entry point without following calls
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Function {
+    address: usize,
+    body: Vec<Statement>,
+}
+
+impl Function {
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+
+    /// Name of the function, `main` for the one at address 0
+    pub fn get_name(&self) -> String {
+        if self.address == 0 {
+            "main".to_string()
+        } else {
+            format!("f{}", self.address)
+        }
+    }
+
+    pub fn get_body(&self) -> &Vec<Statement> {
+        &self.body
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "fn {}() {{", self.get_name())?;
+        write_body(f, &self.body, 1)?;
+        writeln!(f, "}}")
+    }
+}
+
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Pseudocode {
+    functions: Vec<Function>,
+}
+
+impl Pseudocode {
+    /// Functions ordered by address, starting with main
+    pub fn get_functions(&self) -> &Vec<Function> {
+        &self.functions
+    }
+}
+
+impl fmt::Display for Pseudocode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decompile memory with the instruction set of level
+pub fn decompile(level: Level, memory: &[Value]) -> Pseudocode {
+    decompile_with(&create_empty_computer(level), memory)
+}
+
+/// Decompile memory with the instructions registered on computer.
+///
+/// Memory cells become `var_<address>`, relative cells `rb[<offset>]`. Control flow that does
+/// not form an if or a loop is kept as goto
+pub fn decompile_with(computer: &Computer, memory: &[Value]) -> Pseudocode {
+    let cfg = analyze_with(computer, memory);
+    let mut entries = BTreeSet::new();
+    if cfg.get_block(0).is_some() {
+        entries.insert(0);
+    }
+    for block in cfg.get_blocks() {
+        if let Exit::Call {
+            target: Some(target),
+            ..
+        } = block.get_exit()
+        {
+            if cfg.get_block(target).is_some() {
+                entries.insert(target);
+            }
+        }
+    }
+
+    let functions = entries
+        .into_iter()
+        .map(|address| {
+            let blocks = function_blocks(&cfg, address);
+            let mut structurer = Structurer {
+                computer,
+                blocks: &blocks,
+                gotos: BTreeSet::new(),
+                labels: BTreeSet::new(),
+            };
+            let mut body = structurer.range(0, blocks.len(), None, None, None);
+            if !structurer.gotos.is_empty() {
+                // Second pass with labels at every block that is jumped to
+                structurer.labels = std::mem::take(&mut structurer.gotos);
+                body = structurer.range(0, blocks.len(), None, None, None);
+            }
+            Function { address, body }
+        })
+        .collect();
+
+    Pseudocode { functions }
+}
+
+/// Blocks reachable from entry without entering calls, ordered by address with entry first
+fn function_blocks(cfg: &Cfg, entry: usize) -> Vec<&Block> {
+    let mut seen = BTreeMap::new();
+    let mut pending = VecDeque::new();
+    pending.push_back(entry);
+
+    while let Some(address) = pending.pop_front() {
+        if seen.contains_key(&address) {
+            continue;
+        }
+        if let Some(block) = cfg.get_block(address) {
+            seen.insert(address, block);
+            pending.extend(flow_successors(block.get_exit()));
+        }
+    }
+
+    let mut blocks = seen
+        .range(entry..)
+        .map(|(_, block)| *block)
+        .collect::<Vec<_>>();
+    blocks.extend(seen.range(..entry).map(|(_, block)| *block));
+    blocks
+}
+
+/// Successors within the same function
+fn flow_successors(exit: Exit) -> Vec<usize> {
+    match exit {
+        Exit::Call { next, .. } => vec![next],
+        exit => exit.successors(),
+    }
+}
+
+/// Innermost loop statements are generated in: its header and the address after it
+#[derive(Debug, Clone, Copy)]
+struct LoopContext {
+    header: usize,
+    after: Option<usize>,
+}
+
+struct Structurer<'a> {
+    computer: &'a Computer,
+    blocks: &'a [&'a Block],
+    gotos: BTreeSet<usize>,
+    labels: BTreeSet<usize>,
+}
+
+impl<'a> Structurer<'a> {
+    /// Statements of blocks lo..hi. Follow is the address control reaches after the range
+    fn range(
+        &mut self,
+        lo: usize,
+        hi: usize,
+        follow: Option<usize>,
+        context: Option<LoopContext>,
+        header: Option<usize>,
+    ) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        let mut index = lo;
+
+        while index < hi {
+            let block = self.blocks[index];
+            let start = block.get_start();
+            if self.labels.contains(&start) {
+                statements.push(Statement::Label(start));
+            }
+
+            if header != Some(index) {
+                if let Some(end) = self.loop_end(index, hi) {
+                    let after = self.start_of(end + 1, hi, follow);
+                    let loop_context = LoopContext {
+                        header: start,
+                        after,
+                    };
+                    let body =
+                        self.range(index, end + 1, Some(start), Some(loop_context), Some(index));
+                    statements.push(structure_loop(body));
+                    index = end + 1;
+                    if after != self.start_of(index, hi, follow) {
+                        statements.extend(self.jump(
+                            after,
+                            self.start_of(index, hi, follow),
+                            context,
+                        ));
+                    }
+                    continue;
+                }
+            }
+
+            let following = self.start_of(index + 1, hi, follow);
+            statements.extend(block_statements(self.computer, block));
+            match block.get_exit() {
+                Exit::Next(next) => statements.extend(self.jump(Some(next), following, context)),
+                Exit::Halt => statements.push(Statement::Halt),
+                Exit::Call { target, next } => {
+                    statements.push(match target {
+                        Some(target) => Statement::Call(target),
+                        None => Statement::CallDynamic(jump_target(block)),
+                    });
+                    statements.extend(self.jump(Some(next), following, context));
+                }
+                Exit::Jump(Some(target)) => {
+                    statements.extend(self.jump(Some(target), following, context))
+                }
+                Exit::Jump(None) => statements.push(dynamic_jump(block)),
+                Exit::Branch { target, next } => {
+                    let condition = branch_condition(self.computer, block);
+                    let then_end =
+                        target.and_then(|target| self.index_of(target, index + 1, hi, follow));
+                    match then_end {
+                        Some(then_end) if Some(next) == following && then_end > index + 1 => {
+                            let target = target.unwrap_or_default();
+                            let else_end = self.blocks[then_end - 1].get_exit();
+                            let else_end = match else_end {
+                                Exit::Jump(Some(end)) if end > target => {
+                                    self.index_of(end, then_end, hi, follow)
+                                }
+                                _ => None,
+                            };
+                            let (then, otherwise, end) = match else_end {
+                                Some(else_end) => {
+                                    let end = self.start_of(else_end, hi, follow);
+                                    let then = self.range(index + 1, then_end, end, context, None);
+                                    let otherwise =
+                                        self.range(then_end, else_end, end, context, None);
+                                    (then, otherwise, else_end)
+                                }
+                                None => {
+                                    let then = self.range(
+                                        index + 1,
+                                        then_end,
+                                        Some(target),
+                                        context,
+                                        None,
+                                    );
+                                    (then, Vec::new(), then_end)
+                                }
+                            };
+                            statements.push(Statement::If {
+                                condition: condition.negate(),
+                                then,
+                                otherwise,
+                            });
+                            index = end;
+                            continue;
+                        }
+                        _ => {
+                            let jump = match target {
+                                Some(target) => self.jump(Some(target), None, context),
+                                None => vec![dynamic_jump(block)],
+                            };
+                            if target != Some(next) {
+                                statements.push(Statement::If {
+                                    condition,
+                                    then: jump,
+                                    otherwise: Vec::new(),
+                                });
+                            }
+                            statements.extend(self.jump(Some(next), following, context));
+                        }
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        statements
+    }
+
+    /// Last block in index..hi that jumps back to the block at index
+    fn loop_end(&self, index: usize, hi: usize) -> Option<usize> {
+        let start = self.blocks[index].get_start();
+        (index..hi)
+            .rev()
+            .find(|end| flow_successors(self.blocks[*end].get_exit()).contains(&start))
+    }
+
+    /// Address of the block at index, or follow at the end of the range
+    fn start_of(&self, index: usize, hi: usize, follow: Option<usize>) -> Option<usize> {
+        if index < hi {
+            Some(self.blocks[index].get_start())
+        } else {
+            follow
+        }
+    }
+
+    /// Index of the block at address in lo..hi, or hi if address is follow
+    fn index_of(
+        &self,
+        address: usize,
+        lo: usize,
+        hi: usize,
+        follow: Option<usize>,
+    ) -> Option<usize> {
+        (lo..hi)
+            .find(|index| self.blocks[*index].get_start() == address)
+            .or_else(|| {
+                if follow == Some(address) {
+                    Some(hi)
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Statements to continue at target when control would otherwise reach following
+    fn jump(
+        &mut self,
+        target: Option<usize>,
+        following: Option<usize>,
+        context: Option<LoopContext>,
+    ) -> Vec<Statement> {
+        let target = match target {
+            Some(target) if target != following.unwrap_or(usize::MAX) => target,
+            _ => return Vec::new(),
+        };
+        match context {
+            Some(context) if context.header == target => vec![Statement::Continue],
+            Some(context) if context.after == Some(target) => vec![Statement::Break],
+            _ => {
+                self.gotos.insert(target);
+                vec![Statement::Goto(target)]
+            }
+        }
+    }
+}
+
+/// Turn an endless loop into while or do-while if it tests at its start or end
+fn structure_loop(mut body: Vec<Statement>) -> Statement {
+    if let [Statement::If {
+        condition,
+        then,
+        otherwise,
+    }, ..] = body.as_slice()
+    {
+        if then.as_slice() == [Statement::Break] && otherwise.is_empty() {
+            let condition = condition.negate();
+            body.remove(0);
+            return Statement::While { condition, body };
+        }
+    }
+    if let [.., Statement::If {
+        condition,
+        then,
+        otherwise,
+    }, Statement::Break] = body.as_slice()
+    {
+        if then.as_slice() == [Statement::Continue] && otherwise.is_empty() {
+            let condition = condition.clone();
+            body.truncate(body.len() - 2);
+            return Statement::DoWhile { body, condition };
+        }
+    }
+    Statement::Loop(body)
+}
+
+/// Statements of every instruction in block that does not transfer control
+fn block_statements(computer: &Computer, block: &Block) -> Vec<Statement> {
+    let lines = block.get_lines();
+    let count = match block.get_exit() {
+        Exit::Next(_) => lines.len(),
+        // The return address is part of the call
+        Exit::Call { .. } => lines.len().saturating_sub(2),
+        _ => lines.len() - 1,
+    };
+    lines[..count]
+        .iter()
+        .map(|line| statement(computer, line))
+        .collect()
+}
+
+/// Statement of line. Only built-in instructions that are not overridden become expressions
+fn statement(computer: &Computer, line: &Line) -> Statement {
+    let (mnemonic, operands) = match line.get_content() {
+        LineContent::Instruction { mnemonic, operands } => (*mnemonic, operands),
+        LineContent::Data(value) => return Statement::Simple(format!("data({})", value)),
+    };
+    let arg = |index: usize| expression(operands[index]);
+
+    let text = match (standard_code(computer, line), operands.len()) {
+        (Some(1), 3) => match (operands[0], operands[1]) {
+            (a, b) if is_constant(b, 0) => format!("{} = {}", arg(2), expression(a)),
+            (a, b) if is_constant(a, 0) => format!("{} = {}", arg(2), expression(b)),
+            (a, b) if b.get_mode() == ParameterMode::Immediate && b.get_value() < 0 => {
+                format!(
+                    "{} = {} - {}",
+                    arg(2),
+                    expression(a),
+                    -(b.get_value() as i128)
+                )
+            }
+            _ => format!("{} = {} + {}", arg(2), arg(0), arg(1)),
+        },
+        (Some(2), 3) => match (operands[0], operands[1]) {
+            (a, b) if is_constant(b, 1) => format!("{} = {}", arg(2), expression(a)),
+            (a, b) if is_constant(a, 1) => format!("{} = {}", arg(2), expression(b)),
+            _ => format!("{} = {} * {}", arg(2), arg(0), arg(1)),
+        },
+        (Some(7), 3) => format!("{} = {} < {}", arg(2), arg(0), arg(1)),
+        (Some(8), 3) => format!("{} = {} == {}", arg(2), arg(0), arg(1)),
+        (Some(3), 1) => format!("{} = input()", arg(0)),
+        (Some(4), 1) => format!("output({})", arg(0)),
+        (Some(9), 1) => match operands[0] {
+            a if a.get_mode() == ParameterMode::Immediate && a.get_value() < 0 => {
+                format!("rb -= {}", -(a.get_value() as i128))
+            }
+            _ => format!("rb += {}", arg(0)),
+        },
+        _ => format!(
+            "{}({})",
+            mnemonic,
+            operands
+                .iter()
+                .map(|operand| expression(*operand))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    Statement::Simple(text)
+}
+
+fn is_constant(operand: Operand, value: Value) -> bool {
+    operand.get_mode() == ParameterMode::Immediate && operand.get_value() == value
+}
+
+fn expression(operand: Operand) -> String {
+    match operand.get_mode() {
+        ParameterMode::Position => format!("var_{}", operand.get_value()),
+        ParameterMode::Immediate => operand.get_value().to_string(),
+        ParameterMode::Relative => format!("rb[{}]", operand.get_value()),
+    }
+}
+
+fn operands(line: &Line) -> &[Operand] {
+    match line.get_content() {
+        LineContent::Instruction { operands, .. } => operands,
+        LineContent::Data(_) => &[],
+    }
+}
+
+/// Condition under which the branch that ends block is taken.
+/// A comparison right before the branch that writes its condition is inlined
+fn branch_condition(computer: &Computer, block: &Block) -> Condition {
+    let lines = block.get_lines();
+    let branch = &lines[lines.len() - 1];
+    let jump_if_zero = standard_code(computer, branch) == Some(6);
+    let tested = operands(branch)[0];
+
+    if let Some(previous) = lines.len().checked_sub(2).map(|index| &lines[index]) {
+        let compared = match previous.get_content() {
+            LineContent::Instruction { operands, .. } if operands.len() == 3 => {
+                match standard_code(computer, previous) {
+                    Some(7) => Some("<"),
+                    Some(8) => Some("=="),
+                    _ => None,
+                }
+                .filter(|_| operands[2] == tested)
+                .map(|operator| {
+                    Condition::new(expression(operands[0]), operator, expression(operands[1]))
+                })
+            }
+            _ => None,
+        };
+        if let Some(condition) = compared {
+            return if jump_if_zero {
+                condition.negate()
+            } else {
+                condition
+            };
+        }
+    }
+
+    let operator = if jump_if_zero { "==" } else { "!=" };
+    Condition::new(expression(tested), operator, "0".to_string())
+}
+
+fn jump_target(block: &Block) -> String {
+    let lines = block.get_lines();
+    expression(operands(&lines[lines.len() - 1])[1])
+}
+
+/// Jump to an unknown address. Jumps through a relative cell return from a function
+fn dynamic_jump(block: &Block) -> Statement {
+    let lines = block.get_lines();
+    match operands(&lines[lines.len() - 1]).get(1) {
+        Some(target) if target.get_mode() == ParameterMode::Relative => Statement::Return,
+        _ => Statement::GotoDynamic(jump_target(block)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::create_computer;
+    use crate::intcode::instruction::{default_instruction_appy, Instruction};
+
+    fn decompile_source(level: Level, source: &str) -> String {
+        let memory = assemble(level, source).expect("Failed assembling");
+        decompile(level, &memory).to_string()
+    }
+
+    #[test]
+    fn decompile_if_else() {
+        let source = "
+                    in value
+                    lt value, #10, small
+                    jnz small, #then
+                    out #1
+                    jnz #1, #end
+            then:   out #0
+            end:    halt
+            value:  data 0
+            small:  data 0
+        ";
+
+        assert_eq!(
+            decompile_source(Level::Day05, source),
+            "\
+fn main() {
+    var_17 = input();
+    var_18 = var_17 < 10;
+    if (var_17 >= 10) {
+        output(1);
+    } else {
+        output(0);
+    }
+    halt;
+}
+"
+        );
+    }
+
+    #[test]
+    fn decompile_loops() {
+        let source = "
+            loop:   jz n, #done
+                    out n
+                    add n, #-1, n
+                    jnz #1, #loop
+            done:   out #7
+            again:  add n, #1, n
+                    eq n, #3, tmp
+                    jz tmp, #again
+                    halt
+            n:      data 3
+            tmp:    data 0
+        ";
+
+        assert_eq!(
+            decompile_source(Level::Day05, source),
+            "\
+fn main() {
+    while (var_26 != 0) {
+        output(var_26);
+        var_26 = var_26 - 1;
+    }
+    output(7);
+    do {
+        var_26 = var_26 + 1;
+        var_27 = var_26 == 3;
+    } while (var_26 != 3);
+    halt;
+}
+"
+        );
+    }
+
+    #[test]
+    fn decompile_calls() {
+        let source = "
+                    arb #stack
+                    in @1
+                    add #back, #0, @0
+                    jnz #1, #double
+            back:   out @1
+                    halt
+            double: mul @1, #2, @1
+                    jz #0, @0
+            stack:  data 0
+        ";
+        let memory = assemble(Level::Day09, source).expect("Failed assembling");
+        let mut c = create_computer(Level::Day09, memory.clone());
+        c.push_input(21);
+        c.run_until_end().unwrap();
+        assert_eq!(c.get_output(), &[42]);
+
+        let pseudocode = decompile(Level::Day09, &memory);
+        let names = pseudocode
+            .get_functions()
+            .iter()
+            .map(Function::get_name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["main", "f14"]);
+        assert_eq!(
+            pseudocode.to_string(),
+            "\
+fn main() {
+    rb += 21;
+    rb[1] = input();
+    f14();
+    output(rb[1]);
+    halt;
+}
+
+fn f14() {
+    rb[1] = rb[1] * 2;
+    return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn decompile_gotos() {
+        // Jumps out of and back into the middle of a loop
+        let source = "
+            a:      jz x, #b
+                    out #1
+            c:      jz y, #a
+                    halt
+            b:      out #2
+                    jnz #1, #c
+            x:      data 0
+            y:      data 0
+        ";
+        assert_eq!(
+            decompile_source(Level::Day05, source),
+            "\
+fn main() {
+    do {
+        if (var_14 == 0) {
+            goto L9;
+        }
+        output(1);
+    L5:
+    } while (var_15 == 0);
+    halt;
+L9:
+    output(2);
+    goto L5;
+}
+"
+        );
+    }
+
+    #[test]
+    fn decompile_overridden_instruction() {
+        // add replaced by a subtraction with the same code, mnemonic and arity
+        let memory = vec![1101, 5, 3, 0, 4, 0, 99];
+        let mut c = create_computer(Level::Day05, Vec::new());
+        assert!(decompile_with(&c, &memory)
+            .to_string()
+            .contains("var_0 = 5 + 3;"));
+
+        c.add_instruction(Instruction::new(1, "add", 3, |c, p| {
+            default_instruction_appy(c, p, |a, b| a.checked_sub(b))
+        }));
+        assert_eq!(
+            decompile_with(&c, &memory).to_string(),
+            "\
+fn main() {
+    add(5, 3, var_0);
+    output(var_0);
+    halt;
+}
+"
+        );
+    }
+}
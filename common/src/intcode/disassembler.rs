@@ -0,0 +1,315 @@
+use crate::intcode::instruction::ParameterMode;
+use crate::intcode::{create_empty_computer, Code, Computer, Level, Value};
+use std::fmt;
+
+/// Parameter of a disassembled instruction. Written as `5` (position), `#5` or `@5` (relative)
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Operand {
+    mode: ParameterMode,
+    value: Value,
+}
+
+impl Operand {
+    pub fn new(mode: ParameterMode, value: Value) -> Self {
+        Self { mode, value }
+    }
+
+    pub fn get_mode(self) -> ParameterMode {
+        self.mode
+    }
+
+    pub fn get_value(self) -> Value {
+        self.value
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "{}", self.value),
+            ParameterMode::Immediate => write!(f, "#{}", self.value),
+            ParameterMode::Relative => write!(f, "@{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum LineContent {
+    Instruction {
+        mnemonic: &'static str,
+        operands: Vec<Operand>,
+    },
+    /// Value that does not decode into a known instruction
+    Data(Value),
+}
+
+impl fmt::Display for LineContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineContent::Instruction { mnemonic, operands } => {
+                write!(f, "{}", mnemonic)?;
+                for (index, operand) in operands.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, operand)?;
+                }
+                Ok(())
+            }
+            LineContent::Data(value) => write!(f, "data {}", value),
+        }
+    }
+}
+
+/// Single line of a listing: address, decoded content and the raw words it was decoded from
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Line {
+    address: usize,
+    content: LineContent,
+    raw: Vec<Value>,
+}
+
+impl Line {
+    pub fn new(address: usize, content: LineContent, raw: Vec<Value>) -> Self {
+        Self {
+            address,
+            content,
+            raw,
+        }
+    }
+
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+
+    pub fn get_content(&self) -> &LineContent {
+        &self.content
+    }
+
+    pub fn get_raw(&self) -> &Vec<Value> {
+        &self.raw
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let raw = self
+            .raw
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let content = self.content.to_string();
+        write!(f, "{:>5}: {:<24} ; {}", self.address, content, raw)
+    }
+}
+
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Listing {
+    lines: Vec<Line>,
+}
+
+impl Listing {
+    pub fn new(lines: Vec<Line>) -> Self {
+        Self { lines }
+    }
+
+    pub fn get_lines(&self) -> &Vec<Line> {
+        &self.lines
+    }
+
+    /// Listing without addresses and raw words, as accepted by the assembler
+    pub fn to_source(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| format!("{}\n", line.content))
+            .collect()
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Disassemble memory with the instruction set of level
+pub fn disassemble(level: Level, memory: &[Value]) -> Listing {
+    disassemble_with(&create_empty_computer(level), memory)
+}
+
+/// Disassemble memory with the instructions registered on computer.
+/// Words that do not decode into a complete instruction are listed as data
+pub fn disassemble_with(computer: &Computer, memory: &[Value]) -> Listing {
+    disassemble_at(computer, memory, 0)
+}
+
+/// Disassemble up to len words of the memory of computer, starting at address.
+/// Stops at the end of the memory that holds values, see Computer::get_memory_end
+pub fn disassemble_computer(computer: &Computer, address: usize, len: usize) -> Listing {
+    let end = address.saturating_add(len).min(computer.get_memory_end());
+    let memory = (address..end)
+        .map(|address| computer.get(address))
+        .take_while(Option::is_some)
+        .flatten()
+        .collect::<Vec<_>>();
+    disassemble_at(computer, &memory, address)
+}
+
+fn disassemble_at(computer: &Computer, memory: &[Value], offset: usize) -> Listing {
+    let mut lines = Vec::new();
+    let mut address = 0;
+
+    while address < memory.len() {
+        let line = decode_instruction(computer, memory, address, offset).unwrap_or_else(|| {
+            Line::new(
+                offset + address,
+                LineContent::Data(memory[address]),
+                vec![memory[address]],
+            )
+        });
+        address += line.raw.len();
+        lines.push(line);
+    }
+
+    Listing::new(lines)
+}
+
+/// Decode the instruction at address of memory, listed as if memory started at offset
+pub(crate) fn decode_instruction(
+    computer: &Computer,
+    memory: &[Value],
+    address: usize,
+    offset: usize,
+) -> Option<Line> {
+    let opcode = computer.decode(memory[address])?;
+    let instruction_set = computer.get_instruction_set();
+    let arity = instruction_set.arity(opcode.get_code())?;
+    let mnemonic = instruction_set.mnemonic(opcode.get_code())?;
+
+    // Mode digits for parameters the instruction does not have
+    if opcode.get_modes() / 10usize.checked_pow(arity as u32)? != 0 {
+        return None;
+    }
+
+    let raw = memory.get(address..=address + arity)?.to_vec();
+    let operands = raw[1..]
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let mode = opcode.get_mode_for_level(index, computer.get_level())?;
+            Some(Operand::new(mode, *value))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Line::new(
+        offset + address,
+        LineContent::Instruction { mnemonic, operands },
+        raw,
+    ))
+}
+
+/// Code of the instruction of line if the instruction set of computer runs the built-in one for it
+pub(crate) fn standard_code(computer: &Computer, line: &Line) -> Option<Code> {
+    if let LineContent::Data(_) = line.get_content() {
+        return None;
+    }
+    let code = computer.decode(*line.get_raw().first()?)?.get_code();
+    if computer.get_instruction_set().is_standard(code) {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::create_computer;
+
+    #[test]
+    fn disassemble_day02() {
+        let listing = disassemble(Level::Day02, &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        let content = listing
+            .get_lines()
+            .iter()
+            .map(|line| line.get_content().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            content,
+            vec![
+                "add 9, 10, 3",
+                "mul 3, 11, 0",
+                "halt",
+                "data 30",
+                "data 40",
+                "data 50"
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_modes() {
+        let listing = disassemble(Level::Day09, &[1002, 4, 3, 4, 33, 109, -1, 21101]);
+        let lines = listing.get_lines();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0].to_string(),
+            "    0: mul 4, #3, 4             ; 1002,4,3,4"
+        );
+        assert_eq!(lines[1].get_content(), &LineContent::Data(33));
+        assert_eq!(lines[2].get_address(), 5);
+        assert_eq!(lines[2].get_content().to_string(), "arb #-1");
+        assert_eq!(lines[3].get_content(), &LineContent::Data(21101));
+    }
+
+    #[test]
+    fn disassemble_computer_window() {
+        let c = create_computer(Level::Day05, vec![1, 0, 0, 0, 1101, 1, 2, 3, 99]);
+        let listing = disassemble_computer(&c, 4, 100);
+        let lines = listing.get_lines();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].get_address(), 4);
+        assert_eq!(lines[0].get_content().to_string(), "add #1, #2, 3");
+        assert_eq!(lines[1].get_address(), 8);
+    }
+
+    #[test]
+    fn disassemble_computer_bounds() {
+        // Unset memory past the program reads as 0 at Day09, but only set cells are listed
+        let mut c = create_computer(Level::Day09, vec![104, 1, 99]);
+        assert_eq!(
+            disassemble_computer(&c, 0, 1_000_000_000).get_lines().len(),
+            2
+        );
+        c.set(10, 42);
+        assert_eq!(c.get_memory_end(), 11);
+        let listing = disassemble_computer(&c, 9, usize::MAX);
+        assert_eq!(listing.get_lines().len(), 2);
+        assert_eq!(listing.get_lines()[1].get_content(), &LineContent::Data(42));
+
+        assert!(disassemble_computer(&c, usize::MAX, 1)
+            .get_lines()
+            .is_empty());
+    }
+
+    #[test]
+    fn disassemble_level_dependent() {
+        let memory = [204, 3, 99];
+
+        assert_eq!(
+            disassemble(Level::Day09, &memory).get_lines()[0]
+                .get_content()
+                .to_string(),
+            "out @3"
+        );
+        assert_eq!(
+            disassemble(Level::Day05, &memory).get_lines()[0].get_content(),
+            &LineContent::Data(204)
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use crate::intcode::Value;
+use thiserror::Error;
+
+/// Error of a computer while executing the instruction at address
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Error at address {address} (opcode: {}): {reason}", display_opcode(.opcode))]
+pub struct IntcodeError {
+    address: usize,
+    opcode: Option<Value>,
+    reason: ErrorReason,
+}
+
+impl IntcodeError {
+    pub fn new(address: usize, opcode: Option<Value>, reason: ErrorReason) -> Self {
+        Self {
+            address,
+            opcode,
+            reason,
+        }
+    }
+
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+
+    /// Raw opcode at address. None if there was no memory to read it from
+    pub fn get_opcode(&self) -> Option<Value> {
+        self.opcode
+    }
+
+    pub fn get_reason(&self) -> &ErrorReason {
+        &self.reason
+    }
+}
+
+fn display_opcode(opcode: &Option<Value>) -> String {
+    match opcode {
+        Some(opcode) => opcode.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum ErrorReason {
+    #[error("No memory at instruction pointer")]
+    NoInstruction,
+    #[error("Unknown opcode {0}")]
+    UnknownOpcode(Value),
+    #[error("Unknown mode for parameter {0}")]
+    UnknownMode(usize),
+    #[error("Failed to read memory at {address} for parameter {index}")]
+    InvalidRead { address: usize, index: usize },
+    #[error("Parameter {0} is used as address but in immediate mode")]
+    ImmediateAddress(usize),
+    #[error(transparent)]
+    NegativeAddress(#[from] NegativeAddressError),
+    #[error("Instruction pointer set to invalid address {0}")]
+    InvalidPointer(usize),
+    #[error("Arithmetic overflow")]
+    Overflow,
+}
+
+#[derive(Error, Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[error("Negative address {0}")]
+pub struct NegativeAddressError(pub Value);
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Unknown level {0}")]
+pub struct ParseLevelError(pub String);
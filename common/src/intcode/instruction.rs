@@ -1,18 +1,29 @@
-use crate::intcode::{Code, Computer};
+use crate::intcode::{to_address, Code, Computer, ErrorReason, Level, Value};
+use std::convert::TryFrom;
 use std::fmt;
-use std::rc::Rc;
+use std::sync::Arc;
+
+type Action = Arc<dyn Fn(&mut Computer, &Parameters) -> Result<(), ErrorReason> + Send + Sync>;
 
 #[derive(Clone)]
 pub struct Instruction {
     code: Code,
-    action: Rc<dyn Fn(&mut Computer, usize)>,
+    mnemonic: &'static str,
+    arity: usize,
+    action: Action,
 }
 
 impl Instruction {
-    pub fn new<I: 'static + Fn(&mut Computer, usize)>(code: Code, action: I) -> Instruction {
+    /// Create instruction with code, mnemonic and number of parameters for listings
+    pub fn new<I>(code: Code, mnemonic: &'static str, arity: usize, action: I) -> Instruction
+    where
+        I: 'static + Fn(&mut Computer, &Parameters) -> Result<(), ErrorReason> + Send + Sync,
+    {
         Self {
             code,
-            action: Rc::new(action),
+            mnemonic,
+            arity,
+            action: Arc::new(action),
         }
     }
 
@@ -20,57 +31,487 @@ impl Instruction {
         self.code
     }
 
-    pub fn run_instruction(&self, computer: &mut Computer, address: usize) {
-        (self.action)(computer, address)
+    pub fn get_mnemonic(&self) -> &'static str {
+        self.mnemonic
+    }
+
+    pub fn get_arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn run_instruction(
+        &self,
+        computer: &mut Computer,
+        parameters: &Parameters,
+    ) -> Result<(), ErrorReason> {
+        (self.action)(computer, parameters)
     }
 }
 
 impl fmt::Debug for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Instruction {{code: {}, action: ... }}", self.code)
+        write!(
+            f,
+            "Instruction {{code: {}, mnemonic: {}, arity: {}, action: ... }}",
+            self.code, self.mnemonic, self.arity
+        )
     }
 }
 
-pub fn default_instruction_appy<F: Fn(usize, usize) -> usize>(
-    computer: &mut Computer,
+/// Instructions a computer decodes and executes, together with the metadata tools like
+/// the assembler, disassembler and tracer need. Sets can be combined with Extend
+pub trait InstructionSet: Send + Sync {
+    /// Split a raw memory value into code and parameter modes. None if it is no opcode of this set.
+    /// By default the last two digits are the code and every digit before is a mode
+    fn decode(&self, value: Value) -> Option<Opcode> {
+        Opcode::decode(value).filter(|opcode| self.arity(opcode.get_code()).is_some())
+    }
+
+    /// Number of parameters of the instruction with code. None if code is unknown
+    fn arity(&self, code: Code) -> Option<usize>;
+
+    /// Name of the instruction with code, as used in listings. None if code is unknown
+    fn mnemonic(&self, code: Code) -> Option<&'static str>;
+
+    /// Execute the instruction of the opcode of parameters
+    fn execute(&self, computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason>;
+
+    /// Every known code in ascending order
+    fn codes(&self) -> Vec<Code>;
+
+    /// Code of the instruction named mnemonic
+    fn code_of(&self, mnemonic: &str) -> Option<Code> {
+        self.codes()
+            .into_iter()
+            .find(|code| self.mnemonic(*code) == Some(mnemonic))
+    }
+
+    /// Whether code executes the unmodified built-in instruction of Standard.
+    /// Only those may be run by Computer::run_decoded without calling execute
+    fn is_standard(&self, _code: Code) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for dyn InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.codes()
+                    .into_iter()
+                    .map(|code| (code, self.mnemonic(code).unwrap_or_default())),
+            )
+            .finish()
+    }
+}
+
+/// Single instruction as a set of one
+impl InstructionSet for Instruction {
+    fn arity(&self, code: Code) -> Option<usize> {
+        if code == self.code {
+            Some(self.arity)
+        } else {
+            None
+        }
+    }
+
+    fn mnemonic(&self, code: Code) -> Option<&'static str> {
+        if code == self.code {
+            Some(self.mnemonic)
+        } else {
+            None
+        }
+    }
+
+    fn execute(&self, computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+        self.run_instruction(computer, parameters)
+    }
+
+    fn codes(&self) -> Vec<Code> {
+        vec![self.code]
+    }
+}
+
+/// Instructions of the list. The last one wins if codes are used twice, as with Computer::add_instruction
+impl InstructionSet for Vec<Instruction> {
+    fn arity(&self, code: Code) -> Option<usize> {
+        self.iter()
+            .rev()
+            .find_map(|instruction| instruction.arity(code))
+    }
+
+    fn mnemonic(&self, code: Code) -> Option<&'static str> {
+        self.iter()
+            .rev()
+            .find_map(|instruction| instruction.mnemonic(code))
+    }
+
+    fn execute(&self, computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+        let code = parameters.get_opcode().get_code();
+        match self
+            .iter()
+            .rev()
+            .find(|instruction| instruction.code == code)
+        {
+            Some(instruction) => instruction.run_instruction(computer, parameters),
+            None => Err(ErrorReason::UnknownOpcode(code as Value)),
+        }
+    }
+
+    fn codes(&self) -> Vec<Code> {
+        let mut codes = self
+            .iter()
+            .map(|instruction| instruction.code)
+            .collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+        codes
+    }
+}
+
+impl<S: InstructionSet + ?Sized> InstructionSet for Arc<S> {
+    fn decode(&self, value: Value) -> Option<Opcode> {
+        (**self).decode(value)
+    }
+
+    fn arity(&self, code: Code) -> Option<usize> {
+        (**self).arity(code)
+    }
+
+    fn mnemonic(&self, code: Code) -> Option<&'static str> {
+        (**self).mnemonic(code)
+    }
+
+    fn execute(&self, computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+        (**self).execute(computer, parameters)
+    }
+
+    fn codes(&self) -> Vec<Code> {
+        (**self).codes()
+    }
+
+    fn code_of(&self, mnemonic: &str) -> Option<Code> {
+        (**self).code_of(mnemonic)
+    }
+
+    fn is_standard(&self, code: Code) -> bool {
+        (**self).is_standard(code)
+    }
+}
+
+/// Instructions of the first set, extended by those of the second set whose codes the first does not know
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Extend<A, B>(pub A, pub B);
+
+impl<A: InstructionSet, B: InstructionSet> InstructionSet for Extend<A, B> {
+    fn decode(&self, value: Value) -> Option<Opcode> {
+        self.0.decode(value).or_else(|| self.1.decode(value))
+    }
+
+    fn arity(&self, code: Code) -> Option<usize> {
+        self.0.arity(code).or_else(|| self.1.arity(code))
+    }
+
+    fn mnemonic(&self, code: Code) -> Option<&'static str> {
+        self.0.mnemonic(code).or_else(|| self.1.mnemonic(code))
+    }
+
+    fn execute(&self, computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+        if self.0.arity(parameters.get_opcode().get_code()).is_some() {
+            self.0.execute(computer, parameters)
+        } else {
+            self.1.execute(computer, parameters)
+        }
+    }
+
+    fn codes(&self) -> Vec<Code> {
+        let mut codes = self.0.codes();
+        codes.extend(self.1.codes());
+        codes.sort_unstable();
+        codes.dedup();
+        codes
+    }
+
+    fn is_standard(&self, code: Code) -> bool {
+        if self.0.arity(code).is_some() {
+            self.0.is_standard(code)
+        } else {
+            self.1.is_standard(code)
+        }
+    }
+}
+
+/// How the value of a parameter is interpreted
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum ParameterMode {
+    /// Parameter is the address of the value
+    Position,
+    /// Parameter is the value itself
+    Immediate,
+    /// Parameter is the address of the value, offset by the relative base of the computer
+    Relative,
+}
+
+impl ParameterMode {
+    pub fn from_digit(digit: usize) -> Option<Self> {
+        match digit {
+            0 => Some(ParameterMode::Position),
+            1 => Some(ParameterMode::Immediate),
+            2 => Some(ParameterMode::Relative),
+            _ => None,
+        }
+    }
+}
+
+/// Opcode split into the two-digit instruction code and the mode digits of its parameters
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Opcode {
+    code: Code,
+    modes: usize,
+}
+
+impl Opcode {
+    pub fn new(code: Code, modes: usize) -> Self {
+        Self { code, modes }
+    }
+
+    /// Decode a raw memory value: last two digits are the code, every digit before is a mode.
+    /// None for negative values
+    pub fn decode(value: Value) -> Option<Self> {
+        let value = usize::try_from(value).ok()?;
+        Some(Self::new(value % 100, value / 100))
+    }
+
+    pub fn get_code(self) -> Code {
+        self.code
+    }
+
+    pub fn get_modes(self) -> usize {
+        self.modes
+    }
+
+    /// Get mode of parameter at index (starting at 0). None if the digit is no known mode
+    pub fn get_mode(self, index: usize) -> Option<ParameterMode> {
+        let digit = (self.modes / 10usize.pow(index as u32)) % 10;
+        ParameterMode::from_digit(digit)
+    }
+
+    /// Get mode of parameter at index (starting at 0). None if the mode is unknown at level
+    pub fn get_mode_for_level(self, index: usize, level: Level) -> Option<ParameterMode> {
+        match self.get_mode(index) {
+            Some(ParameterMode::Relative) if level < Level::Day09 => None,
+            mode => mode,
+        }
+    }
+}
+
+/// Parameters of the instruction at address, resolved with the modes of its opcode
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Parameters {
     address: usize,
-    todo: F,
-) {
-    let addr1 = computer.get(address + 1).unwrap_or_else(|| {
-        panic!(
-            "Failed to get value at {} for first address of operation at {}",
-            address + 1,
-            address
-        )
-    });
-    let addr2 = computer.get(address + 2).unwrap_or_else(|| {
-        panic!(
-            "Failed to get value at {} for second address of operation at {}",
-            address + 2,
-            address
-        )
-    });
-    let addr_res = computer.get(address + 3).unwrap_or_else(|| {
-        panic!(
-            "Failed to get value at {} for result address of operation at {}",
-            address + 3,
-            address
-        )
-    });
+    opcode: Opcode,
+}
 
-    let value1 = computer.get(addr1).unwrap_or_else(|| {
-        panic!(
-            "Failed to get value at {} for first value of operation at {}",
-            addr1, address
-        )
-    });
-    let value2 = computer.get(addr2).unwrap_or_else(|| {
-        panic!(
-            "Failed to get value at {} for first value of operation at {}",
-            addr2, address
-        )
-    });
+impl Parameters {
+    pub fn new(address: usize, opcode: Opcode) -> Self {
+        Self { address, opcode }
+    }
+
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+
+    pub fn get_opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    fn get_mode(&self, computer: &Computer, index: usize) -> Result<ParameterMode, ErrorReason> {
+        self.opcode
+            .get_mode_for_level(index, computer.get_level())
+            .ok_or(ErrorReason::UnknownMode(index))
+    }
+
+    /// Value of parameter at index (starting at 0) as written in memory
+    pub fn get_raw(&self, computer: &Computer, index: usize) -> Result<Value, ErrorReason> {
+        let address = self.address + 1 + index;
+        computer
+            .get(address)
+            .ok_or(ErrorReason::InvalidRead { address, index })
+    }
+
+    /// Value of parameter at index (starting at 0) resolved with its mode
+    pub fn get_value(&self, computer: &Computer, index: usize) -> Result<Value, ErrorReason> {
+        if self.get_mode(computer, index)? == ParameterMode::Immediate {
+            return self.get_raw(computer, index);
+        }
+        let address = self.get_write_address(computer, index)?;
+        computer
+            .get(address)
+            .ok_or(ErrorReason::InvalidRead { address, index })
+    }
+
+    /// Address parameter at index (starting at 0) points to. Used for writing results
+    pub fn get_write_address(
+        &self,
+        computer: &Computer,
+        index: usize,
+    ) -> Result<usize, ErrorReason> {
+        let address = match self.get_mode(computer, index)? {
+            ParameterMode::Position => self.get_raw(computer, index)?,
+            ParameterMode::Relative => computer
+                .get_relative_base()
+                .checked_add(self.get_raw(computer, index)?)
+                .ok_or(ErrorReason::Overflow)?,
+            ParameterMode::Immediate => return Err(ErrorReason::ImmediateAddress(index)),
+        };
+        Ok(to_address(address)?)
+    }
+}
+
+pub fn default_instruction_appy<F: Fn(Value, Value) -> Option<Value>>(
+    computer: &mut Computer,
+    parameters: &Parameters,
+    todo: F,
+) -> Result<(), ErrorReason> {
+    let value1 = parameters.get_value(computer, 0)?;
+    let value2 = parameters.get_value(computer, 1)?;
+    let addr_res = parameters.get_write_address(computer, 2)?;
 
-    computer.set(addr_res, todo(value1, value2));
+    computer.set(addr_res, todo(value1, value2).ok_or(ErrorReason::Overflow)?);
     computer.increase_ptr(4);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assembler::assemble_with;
+    use crate::intcode::disassembler::disassemble_with;
+    use crate::intcode::trace::StepEvent;
+    use crate::intcode::{create_computer, ComputerState, Standard};
+    use std::sync::Mutex;
+
+    /// Dialect with "swap a, b" (20) and the four parameter "sum a, b, c, result" (21)
+    struct Extra;
+
+    impl InstructionSet for Extra {
+        fn arity(&self, code: Code) -> Option<usize> {
+            match code {
+                20 => Some(2),
+                21 => Some(4),
+                _ => None,
+            }
+        }
+
+        fn mnemonic(&self, code: Code) -> Option<&'static str> {
+            match code {
+                20 => Some("swap"),
+                21 => Some("sum"),
+                _ => None,
+            }
+        }
+
+        fn execute(
+            &self,
+            computer: &mut Computer,
+            parameters: &Parameters,
+        ) -> Result<(), ErrorReason> {
+            match parameters.get_opcode().get_code() {
+                20 => {
+                    let a = parameters.get_write_address(computer, 0)?;
+                    let b = parameters.get_write_address(computer, 1)?;
+                    let value_a = parameters.get_value(computer, 0)?;
+                    let value_b = parameters.get_value(computer, 1)?;
+                    computer.set(a, value_b);
+                    computer.set(b, value_a);
+                    computer.increase_ptr(3);
+                }
+                _ => {
+                    let mut sum: Value = 0;
+                    for index in 0..3 {
+                        sum = sum
+                            .checked_add(parameters.get_value(computer, index)?)
+                            .ok_or(ErrorReason::Overflow)?;
+                    }
+                    computer.set(parameters.get_write_address(computer, 3)?, sum);
+                    computer.increase_ptr(5);
+                }
+            }
+            Ok(())
+        }
+
+        fn codes(&self) -> Vec<Code> {
+            vec![20, 21]
+        }
+    }
+
+    #[test]
+    fn standard_instruction_set() {
+        assert_eq!(Standard::new(Level::Day02).codes(), vec![1, 2, 99]);
+        assert_eq!(Standard::new(Level::Day09).codes().len(), 10);
+        assert_eq!(Standard::new(Level::Day05).mnemonic(8), Some("eq"));
+        assert_eq!(Standard::new(Level::Day05).arity(9), None);
+        assert_eq!(Standard::new(Level::Day05).code_of("jz"), Some(6));
+        assert_eq!(Standard::new(Level::Day02).decode(1002), None);
+        assert_eq!(
+            Standard::new(Level::Day05).decode(1002),
+            Some(Opcode::new(2, 10))
+        );
+    }
+
+    #[test]
+    fn custom_dialect() {
+        let source = "
+                    sum a, b, #5, c
+                    swap a, c
+                    out a
+                    out c
+                    halt
+            a:      data 1
+            b:      data 2
+            c:      data 0
+        ";
+        let dialect = Extend(Extra, Standard::new(Level::Day05));
+        let mut c = Computer::with_instruction_set(Level::Day05, Vec::new(), dialect);
+        let memory = assemble_with(&c, source).expect("Failed assembling");
+        assert_eq!(&memory[..5], &[10021, 13, 14, 5, 15]);
+
+        let listing = disassemble_with(&c, &memory);
+        assert_eq!(
+            listing.get_lines()[0].get_content().to_string(),
+            "sum 13, 14, #5, 15"
+        );
+        assert_eq!(assemble_with(&c, &listing.to_source()), Ok(memory.clone()));
+
+        let mnemonics = Arc::new(Mutex::new(Vec::new()));
+        let sink = mnemonics.clone();
+        c.set_memory(memory);
+        c.set_observer(move |event: &StepEvent| sink.lock().unwrap().push(event.get_mnemonic()));
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[8, 1]);
+        assert_eq!(
+            *mnemonics.lock().unwrap(),
+            vec![
+                Some("sum"),
+                Some("swap"),
+                Some("out"),
+                Some("out"),
+                Some("halt")
+            ]
+        );
+    }
+
+    #[test]
+    fn add_instruction_replaces() {
+        let mut c = create_computer(Level::Day05, vec![1101, 5, 3, 0, 4, 0, 99]);
+        c.add_instruction(Instruction::new(1, "sub", 3, |computer, parameters| {
+            default_instruction_appy(computer, parameters, |a, b| a.checked_sub(b))
+        }));
+
+        assert_eq!(c.get_instruction_set().mnemonic(1), Some("sub"));
+        assert_eq!(c.get_instruction_set().code_of("add"), None);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[2]);
+    }
 }
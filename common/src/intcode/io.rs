@@ -0,0 +1,104 @@
+use crate::intcode::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Source of input values for a computer, asked when its input queue is empty
+pub trait InputSource: Send {
+    /// Next input value. None if there is currently no input available
+    fn read(&mut self) -> Option<Value>;
+}
+
+/// Sink for output values of a computer, used instead of its output buffer
+pub trait OutputSink: Send {
+    fn write(&mut self, value: Value);
+}
+
+impl fmt::Debug for dyn InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InputSource")
+    }
+}
+
+impl fmt::Debug for dyn OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OutputSink")
+    }
+}
+
+impl InputSource for VecDeque<Value> {
+    fn read(&mut self) -> Option<Value> {
+        self.pop_front()
+    }
+}
+
+impl<F: FnMut() -> Option<Value> + Send> InputSource for F {
+    fn read(&mut self) -> Option<Value> {
+        self()
+    }
+}
+
+/// Appends to the shared list, so a clone of the handle can read the values
+impl OutputSink for Arc<Mutex<Vec<Value>>> {
+    fn write(&mut self, value: Value) {
+        self.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(value)
+    }
+}
+
+impl<F: FnMut(Value) + Send> OutputSink for F {
+    fn write(&mut self, value: Value) {
+        self(value)
+    }
+}
+
+/// Blocks until a value is received. No input once every sender is dropped
+impl InputSource for Receiver<Value> {
+    fn read(&mut self) -> Option<Value> {
+        self.recv().ok()
+    }
+}
+
+/// Values sent after the receiver is dropped are lost
+impl OutputSink for Sender<Value> {
+    fn write(&mut self, value: Value) {
+        let _ = self.send(value);
+    }
+}
+
+/// Blocks while the channel is full. Values sent after the receiver is dropped are lost
+impl OutputSink for SyncSender<Value> {
+    fn write(&mut self, value: Value) {
+        let _ = self.send(value);
+    }
+}
+
+/// Reads one value per line from stdin. Lines that fail to parse are skipped, EOF means no input
+#[derive(Debug, Default, Copy, Clone)]
+pub struct StdinSource;
+
+impl InputSource for StdinSource {
+    fn read(&mut self) -> Option<Value> {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line.ok()?.trim().parse::<Value>() {
+                Ok(value) => return Some(value),
+                Err(err) => eprintln!("Invalid input: {}", err),
+            }
+        }
+        None
+    }
+}
+
+/// Prints every value on its own line to stdout
+#[derive(Debug, Default, Copy, Clone)]
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn write(&mut self, value: Value) {
+        println!("{}", value)
+    }
+}
@@ -0,0 +1,171 @@
+use crate::intcode::varint::{read_values, write_values, VarintError};
+use crate::intcode::Value;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path;
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"ICPG";
+const VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Invalid value '{token}' at token {index} (byte {offset})")]
+    InvalidValue {
+        index: usize,
+        offset: usize,
+        token: String,
+    },
+    #[error("Program is neither text nor compact: {0}")]
+    InvalidText(#[from] std::str::Utf8Error),
+    #[error("Unsupported compact program version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Number does not fit into 64 bits")]
+    InvalidNumber,
+}
+
+impl From<VarintError> for LoadError {
+    fn from(err: VarintError) -> Self {
+        match err {
+            VarintError::Io(err) => LoadError::Io(err),
+            VarintError::Overflow => LoadError::InvalidNumber,
+        }
+    }
+}
+
+/// Parse comma separated values. Whitespace around values and a trailing comma are allowed,
+/// every other token has to be a number
+pub fn parse(text: &str) -> Result<Vec<Value>, LoadError> {
+    let mut memory = Vec::new();
+    let mut offset = 0;
+    let tokens = text.split(',').collect::<Vec<_>>();
+
+    for (index, token) in tokens.iter().enumerate() {
+        let value = token.trim();
+        let start = offset + token.len() - token.trim_start().len();
+        offset += token.len() + 1;
+
+        if value.is_empty() && index + 1 == tokens.len() {
+            break;
+        }
+        match value.parse::<Value>() {
+            Ok(value) => memory.push(value),
+            Err(_) => {
+                return Err(LoadError::InvalidValue {
+                    index,
+                    offset: start,
+                    token: value.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(memory)
+}
+
+/// Read a program in compact format: the magic `ICPG`, a version byte and the values
+/// as length prefixed list of zigzag encoded LEB128 varints
+pub fn read_compact<R: Read>(mut reader: R) -> Result<Vec<Value>, LoadError> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a compact program").into());
+    }
+    if header[4] != VERSION {
+        return Err(LoadError::UnsupportedVersion(header[4]));
+    }
+    Ok(read_values(&mut reader)?)
+}
+
+pub fn write_compact<W: Write>(memory: &[Value], mut writer: W) -> io::Result<()> {
+    let mut buffer = Vec::with_capacity(memory.len() + MAGIC.len() + 1);
+    buffer.extend_from_slice(MAGIC);
+    buffer.push(VERSION);
+    write_values(&mut buffer, memory);
+    writer.write_all(&buffer)
+}
+
+/// Load a program from file, in compact format if it starts with its magic, as text otherwise
+pub fn load<P: AsRef<path::Path>>(path: P) -> Result<Vec<Value>, LoadError> {
+    let content = fs::read(path)?;
+    if content.starts_with(MAGIC) {
+        read_compact(content.as_slice())
+    } else {
+        parse(std::str::from_utf8(&content)?)
+    }
+}
+
+pub fn save_compact<P: AsRef<path::Path>>(memory: &[Value], path: P) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    write_compact(memory, &mut writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_text() {
+        assert_eq!(
+            parse("1,9, 10,3,\n2 ,3,11,0,99\n").unwrap(),
+            vec![1, 9, 10, 3, 2, 3, 11, 0, 99]
+        );
+        assert_eq!(parse("104,-5,99,\n").unwrap(), vec![104, -5, 99]);
+        assert_eq!(parse("  \n").unwrap(), Vec::<Value>::new());
+    }
+
+    #[test]
+    fn parse_errors() {
+        match parse("1,2,\n3x,4") {
+            Err(LoadError::InvalidValue {
+                index,
+                offset,
+                token,
+            }) => {
+                assert_eq!((index, offset, token.as_str()), (2, 5, "3x"));
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(
+            parse("1,,2").unwrap_err().to_string(),
+            "Invalid value '' at token 1 (byte 2)"
+        );
+        assert!(parse("1,2,,").is_err());
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let memory = vec![
+            109,
+            1,
+            204,
+            -1,
+            1001,
+            100,
+            1,
+            100,
+            Value::MAX,
+            Value::MIN,
+            0,
+        ];
+        let mut buffer = Vec::new();
+        write_compact(&memory, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..5], b"ICPG\x01");
+        assert!(buffer.len() < 5 + 1 + memory.len() * 2 + 18);
+        assert_eq!(read_compact(buffer.as_slice()).unwrap(), memory);
+
+        buffer[4] = 7;
+        assert!(matches!(
+            read_compact(buffer.as_slice()),
+            Err(LoadError::UnsupportedVersion(7))
+        ));
+        assert!(matches!(
+            read_compact(&b"ICPG\x01\x05\x02"[..]),
+            Err(LoadError::Io(_))
+        ));
+    }
+}
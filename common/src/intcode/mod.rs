@@ -1,61 +1,264 @@
+pub mod amplifier;
+pub mod assembler;
+pub mod cfg;
 mod computer;
+pub mod decompiler;
+pub mod disassembler;
+mod error;
 pub mod instruction;
+pub mod io;
+pub mod loader;
+pub mod network;
+#[cfg(feature = "async")]
+pub mod runner;
+pub mod session;
+pub mod snapshot;
+pub mod trace;
+mod varint;
 
-use crate::intcode::instruction::{default_instruction_appy, Instruction};
+use crate::intcode::instruction::{default_instruction_appy, InstructionSet, Opcode, Parameters};
+use std::convert::TryFrom;
+use std::str::FromStr;
 
 pub use computer::Computer;
 pub use computer::ComputerState;
+pub use error::{ErrorReason, IntcodeError, NegativeAddressError, ParseLevelError};
 
 pub type Code = usize;
 
-pub fn create_computer(level: Level, memory: Vec<usize>) -> Computer {
+/// Type of a single memory cell
+pub type Value = i64;
+
+/// Convert a memory value into an address
+pub fn to_address(value: Value) -> Result<usize, NegativeAddressError> {
+    usize::try_from(value).map_err(|_| NegativeAddressError(value))
+}
+
+pub fn create_computer(level: Level, memory: Vec<Value>) -> Computer {
     let mut c = create_empty_computer(level);
     c.set_memory(memory);
     c
 }
 
 pub fn create_empty_computer(level: Level) -> Computer {
-    let mut instructions = Vec::with_capacity(1);
-    instructions.push(Instruction::new(99, end_instruction));
+    Computer::with_instruction_set(level, Vec::new(), Standard::new(level))
+}
+
+/// Code, mnemonic, arity and first level of every built-in instruction
+const STANDARD_INSTRUCTIONS: [(Code, &str, usize, Level); 10] = [
+    (1, "add", 3, Level::Day02),
+    (2, "mul", 3, Level::Day02),
+    (3, "in", 1, Level::InputOutput),
+    (4, "out", 1, Level::InputOutput),
+    (5, "jnz", 2, Level::Day05),
+    (6, "jz", 2, Level::Day05),
+    (7, "lt", 3, Level::Day05),
+    (8, "eq", 3, Level::Day05),
+    (9, "arb", 1, Level::Day09),
+    (99, "halt", 0, Level::None),
+];
+
+/// Built-in instructions of a level. Below Level::ParameterModes the whole value is the code
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Standard {
+    level: Level,
+}
 
-    let level = level as usize;
+impl Standard {
+    pub fn new(level: Level) -> Self {
+        Self { level }
+    }
 
-    if level > 0 {
-        instructions.reserve(2);
-        instructions.push(Instruction::new(1, add_instruction));
-        instructions.push(Instruction::new(2, mul_instruction));
+    pub fn get_level(self) -> Level {
+        self.level
     }
 
-    Computer::new_with_instructions(Vec::new(), instructions)
+    fn find(self, code: Code) -> Option<&'static (Code, &'static str, usize, Level)> {
+        STANDARD_INSTRUCTIONS
+            .iter()
+            .find(|(known, _, _, level)| *known == code && self.level >= *level)
+    }
 }
 
+impl InstructionSet for Standard {
+    fn decode(&self, value: Value) -> Option<Opcode> {
+        let opcode = if self.level >= Level::ParameterModes {
+            Opcode::decode(value)?
+        } else {
+            Opcode::new(Code::try_from(value).ok()?, 0)
+        };
+        self.find(opcode.get_code())?;
+        Some(opcode)
+    }
+
+    fn arity(&self, code: Code) -> Option<usize> {
+        self.find(code).map(|(_, _, arity, _)| *arity)
+    }
+
+    fn mnemonic(&self, code: Code) -> Option<&'static str> {
+        self.find(code).map(|(_, mnemonic, _, _)| *mnemonic)
+    }
+
+    fn execute(&self, computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+        let code = parameters.get_opcode().get_code();
+        if self.find(code).is_none() {
+            return Err(ErrorReason::UnknownOpcode(code as Value));
+        }
+        match code {
+            1 => add_instruction(computer, parameters),
+            2 => mul_instruction(computer, parameters),
+            3 => input_instruction(computer, parameters),
+            4 => output_instruction(computer, parameters),
+            5 => jump_if_true_instruction(computer, parameters),
+            6 => jump_if_false_instruction(computer, parameters),
+            7 => less_than_instruction(computer, parameters),
+            8 => equals_instruction(computer, parameters),
+            9 => adjust_relative_base_instruction(computer, parameters),
+            99 => end_instruction(computer, parameters),
+            _ => Err(ErrorReason::UnknownOpcode(code as Value)),
+        }
+    }
+
+    fn codes(&self) -> Vec<Code> {
+        let mut codes = STANDARD_INSTRUCTIONS
+            .iter()
+            .filter(|(_, _, _, level)| self.level >= *level)
+            .map(|(code, _, _, _)| *code)
+            .collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes
+    }
+
+    fn is_standard(&self, code: Code) -> bool {
+        self.find(code).is_some()
+    }
+}
+
+/// Instruction set and decoding a computer supports. Every level contains all lower ones
 #[non_exhaustive]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Level {
     None = 0,
     Day02 = 1,
+    /// Day02 instructions with parameter modes (position/immediate) decoded from the opcode
+    ParameterModes = 2,
+    /// Input and output instructions of Day05 part one
+    InputOutput = 3,
+    /// Jump and comparison instructions of Day05 part two
+    Day05 = 4,
+    /// Relative base with relative parameter mode of Day09. Memory past the program reads as 0
+    Day09 = 5,
 }
 
-fn add_instruction(computer: &mut Computer, address: usize) {
-    default_instruction_appy(computer, address, |a, b| a + b)
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Level::None),
+            "day02" => Ok(Level::Day02),
+            "parametermodes" | "parameter-modes" => Ok(Level::ParameterModes),
+            "inputoutput" | "input-output" => Ok(Level::InputOutput),
+            "day05" => Ok(Level::Day05),
+            "day09" => Ok(Level::Day09),
+            _ => Err(ParseLevelError(s.to_string())),
+        }
+    }
+}
+
+fn add_instruction(computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+    default_instruction_appy(computer, parameters, |a, b| a.checked_add(b))
+}
+
+fn mul_instruction(computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+    default_instruction_appy(computer, parameters, |a, b| a.checked_mul(b))
+}
+
+fn input_instruction(computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+    let address = parameters.get_write_address(computer, 0)?;
+    match computer.read_input() {
+        Some(value) => {
+            computer.set(address, value);
+            computer.set_status(ComputerState::Runnable);
+            computer.increase_ptr(2);
+        }
+        None => computer.set_status(ComputerState::WaitingForInput),
+    }
+    Ok(())
+}
+
+fn output_instruction(computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+    let value = parameters.get_value(computer, 0)?;
+    computer.write_output(value);
+    computer.increase_ptr(2);
+    Ok(())
+}
+
+fn jump_if_true_instruction(
+    computer: &mut Computer,
+    parameters: &Parameters,
+) -> Result<(), ErrorReason> {
+    if parameters.get_value(computer, 0)? != 0 {
+        let target = parameters.get_value(computer, 1)?;
+        computer.set_ptr(to_address(target)?)
+    } else {
+        computer.increase_ptr(3);
+        Ok(())
+    }
+}
+
+fn jump_if_false_instruction(
+    computer: &mut Computer,
+    parameters: &Parameters,
+) -> Result<(), ErrorReason> {
+    if parameters.get_value(computer, 0)? == 0 {
+        let target = parameters.get_value(computer, 1)?;
+        computer.set_ptr(to_address(target)?)
+    } else {
+        computer.increase_ptr(3);
+        Ok(())
+    }
 }
 
-fn mul_instruction(computer: &mut Computer, address: usize) {
-    default_instruction_appy(computer, address, |a, b| a * b)
+fn less_than_instruction(
+    computer: &mut Computer,
+    parameters: &Parameters,
+) -> Result<(), ErrorReason> {
+    default_instruction_appy(computer, parameters, |a, b| Some((a < b) as Value))
 }
 
-fn end_instruction(computer: &mut Computer, _: usize) {
-    computer.set_status(ComputerState::Finished)
+fn equals_instruction(computer: &mut Computer, parameters: &Parameters) -> Result<(), ErrorReason> {
+    default_instruction_appy(computer, parameters, |a, b| Some((a == b) as Value))
+}
+
+fn adjust_relative_base_instruction(
+    computer: &mut Computer,
+    parameters: &Parameters,
+) -> Result<(), ErrorReason> {
+    let value = parameters.get_value(computer, 0)?;
+    computer.adjust_relative_base(value)?;
+    computer.increase_ptr(2);
+    Ok(())
+}
+
+fn end_instruction(computer: &mut Computer, _: &Parameters) -> Result<(), ErrorReason> {
+    computer.set_status(ComputerState::Finished);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn computer_test1() {
         let mut c = create_computer(Level::Day02, vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
 
-        assert_eq!(c.run_until_end(), ComputerState::Finished);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
         assert_eq!(c.get(0), Some(3500));
     }
 
@@ -63,7 +266,7 @@ mod tests {
     fn computer_test2() {
         let mut c = create_computer(Level::Day02, vec![1, 0, 0, 0, 99]);
 
-        assert_eq!(c.run_until_end(), ComputerState::Finished);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
         assert_eq!(c.get(0), Some(2));
     }
 
@@ -71,7 +274,7 @@ mod tests {
     fn computer_test3() {
         let mut c = create_computer(Level::Day02, vec![2, 3, 0, 3, 99]);
 
-        assert_eq!(c.run_until_end(), ComputerState::Finished);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
         assert_eq!(c.get(3), Some(6));
     }
 
@@ -79,7 +282,7 @@ mod tests {
     fn computer_test4() {
         let mut c = create_computer(Level::Day02, vec![2, 4, 4, 5, 99, 0]);
 
-        assert_eq!(c.run_until_end(), ComputerState::Finished);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
         assert_eq!(c.get(5), Some(9801));
     }
 
@@ -87,7 +290,382 @@ mod tests {
     fn computer_test5() {
         let mut c = create_computer(Level::Day02, vec![1, 1, 1, 4, 99, 5, 6, 0, 99]);
 
-        assert_eq!(c.run_until_end(), ComputerState::Finished);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
         assert_eq!(c.get(0), Some(30));
     }
+
+    #[test]
+    fn computer_test_modes1() {
+        let mut c = create_computer(Level::ParameterModes, vec![1002, 4, 3, 4, 33]);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get(4), Some(99));
+    }
+
+    #[test]
+    fn computer_test_modes2() {
+        let mut c = create_computer(Level::ParameterModes, vec![1101, 98, 1, 4, 0]);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get(4), Some(99));
+    }
+
+    #[test]
+    fn computer_test_modes_day02() {
+        let mut c = create_computer(
+            Level::ParameterModes,
+            vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+        );
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get(0), Some(3500));
+    }
+
+    #[test]
+    fn computer_test_io1() {
+        let mut c = create_computer(Level::InputOutput, vec![3, 0, 4, 0, 99]);
+        c.push_input(42);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[42]);
+    }
+
+    #[test]
+    fn computer_test_io_waiting() {
+        let mut c = create_computer(Level::InputOutput, vec![3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+        assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+        c.push_input(5);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.take_output(), vec![15]);
+        assert!(c.get_output().is_empty());
+    }
+
+    #[test]
+    fn computer_test_io_source_sink() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+
+        let mut c = create_computer(Level::InputOutput, vec![3, 0, 4, 0, 3, 0, 4, 0, 99]);
+        c.set_input_source(VecDeque::from(vec![7, 8]));
+        c.set_output_sink(Arc::clone(&output));
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(*output.lock().unwrap(), vec![7, 8]);
+        assert!(c.get_output().is_empty());
+    }
+
+    #[test]
+    fn computer_test_threads() {
+        let (input, a_in) = mpsc::channel();
+        let (a_out, b_in) = mpsc::channel();
+        let (b_out, output) = mpsc::channel();
+
+        // Both double their input, connected by channels on their own threads
+        let handles = vec![(a_in, a_out), (b_in, b_out)]
+            .into_iter()
+            .map(|(source, sink)| {
+                let mut c = create_computer(Level::Day05, vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0]);
+                c.set_input_source(source);
+                c.set_output_sink(sink);
+                thread::spawn(move || c.run_until_end())
+            })
+            .collect::<Vec<_>>();
+
+        input.send(5).unwrap();
+        assert_eq!(output.recv(), Ok(20));
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Ok(ComputerState::Finished));
+        }
+    }
+
+    fn test_single_io(memory: &[Value], input: Value, output: Value) {
+        let mut c = create_computer(Level::Day05, memory.to_vec());
+        c.push_input(input);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[output]);
+    }
+
+    #[test]
+    fn computer_test_compare1() {
+        let memory = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        test_single_io(&memory, 8, 1);
+        test_single_io(&memory, 7, 0);
+    }
+
+    #[test]
+    fn computer_test_compare2() {
+        let memory = [3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+        test_single_io(&memory, 7, 1);
+        test_single_io(&memory, 8, 0);
+    }
+
+    #[test]
+    fn computer_test_compare3() {
+        let memory = [3, 3, 1108, -1, 8, 3, 4, 3, 99];
+        test_single_io(&memory, 8, 1);
+        test_single_io(&memory, 9, 0);
+    }
+
+    #[test]
+    fn computer_test_compare4() {
+        let memory = [3, 3, 1107, -1, 8, 3, 4, 3, 99];
+        test_single_io(&memory, 3, 1);
+        test_single_io(&memory, 8, 0);
+    }
+
+    #[test]
+    fn computer_test_jump1() {
+        let memory = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        test_single_io(&memory, 0, 0);
+        test_single_io(&memory, 5, 1);
+    }
+
+    #[test]
+    fn computer_test_jump2() {
+        let memory = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+        test_single_io(&memory, 0, 0);
+        test_single_io(&memory, 5, 1);
+    }
+
+    #[test]
+    fn computer_test_jump_compare() {
+        let memory = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        test_single_io(&memory, 7, 999);
+        test_single_io(&memory, 8, 1000);
+        test_single_io(&memory, 9, 1001);
+    }
+
+    #[test]
+    fn computer_test_jump_invalid() {
+        let mut c = create_computer(Level::Day05, vec![1105, 1, 100, 99]);
+
+        let error = IntcodeError::new(0, Some(1105), ErrorReason::InvalidPointer(100));
+        assert_eq!(c.run_until_end(), Err(error.clone()));
+        assert_eq!(c.get_status(), ComputerState::Error);
+        assert_eq!(c.get_error(), Some(&error));
+        assert_eq!(c.get_ptr(), 0);
+    }
+
+    #[test]
+    fn computer_test_large_numbers1() {
+        let mut c = create_computer(
+            Level::Day09,
+            vec![1102, 34_915_192, 34_915_192, 7, 4, 7, 99, 0],
+        );
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[1_219_070_632_396_864]);
+    }
+
+    #[test]
+    fn computer_test_large_numbers2() {
+        let mut c = create_computer(Level::Day09, vec![104, 1_125_899_906_842_624, 99]);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[1_125_899_906_842_624]);
+    }
+
+    #[test]
+    fn computer_test_relative() {
+        let mut c = create_computer(Level::Day09, vec![109, 10, 203, 5, 204, 5, 99]);
+        c.push_input(23);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_relative_base(), 10);
+        assert_eq!(c.get(15), Some(23));
+        assert_eq!(c.get_output(), &[23]);
+    }
+
+    #[test]
+    fn computer_test_extended_memory() {
+        let mut c = create_computer(Level::Day09, vec![4, 100, 99]);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[0]);
+
+        let c = create_computer(Level::Day05, vec![4, 100, 99]);
+        assert_eq!(c.get(100), None);
+    }
+
+    #[test]
+    fn computer_test_negative() {
+        let mut c = create_computer(Level::ParameterModes, vec![1101, 100, -1, 4, 0]);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get(4), Some(99));
+    }
+
+    #[test]
+    fn computer_test_quine() {
+        let memory = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut c = create_computer(Level::Day09, memory.clone());
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), memory.as_slice());
+    }
+
+    #[test]
+    fn computer_test_negative_address() {
+        let mut c = create_computer(Level::Day05, vec![1, -1, 0, 0, 99]);
+
+        assert_eq!(
+            c.run_until_end(),
+            Err(IntcodeError::new(
+                0,
+                Some(1),
+                ErrorReason::NegativeAddress(NegativeAddressError(-1))
+            ))
+        );
+    }
+
+    #[test]
+    fn computer_test_negative_jump() {
+        let mut c = create_computer(Level::Day05, vec![1105, 1, -4, 99]);
+
+        assert_eq!(
+            c.run_until_end(),
+            Err(IntcodeError::new(
+                0,
+                Some(1105),
+                ErrorReason::NegativeAddress(NegativeAddressError(-4))
+            ))
+        );
+    }
+
+    #[test]
+    fn computer_test_unknown_opcode() {
+        let mut c = create_computer(Level::Day02, vec![1002, 4, 3, 4, 33]);
+
+        let error = IntcodeError::new(0, Some(1002), ErrorReason::UnknownOpcode(1002));
+        assert_eq!(c.run_until_end(), Err(error.clone()));
+        assert_eq!(c.step(), Err(error));
+    }
+
+    #[test]
+    fn computer_test_unknown_mode() {
+        let mut c = create_computer(Level::Day05, vec![1, 0, 0, 0, 204, 0, 99]);
+
+        assert_eq!(
+            c.run_until_end(),
+            Err(IntcodeError::new(4, Some(204), ErrorReason::UnknownMode(0)))
+        );
+        assert_eq!(c.get(0), Some(2));
+    }
+
+    #[test]
+    fn computer_test_empty_memory() {
+        let mut c = create_computer(Level::Day02, Vec::new());
+
+        assert_eq!(c.get(0), None);
+        assert_eq!(
+            c.run_until_end(),
+            Err(IntcodeError::new(0, None, ErrorReason::NoInstruction))
+        );
+    }
+
+    #[test]
+    fn computer_test_overflow() {
+        let mut c = create_computer(Level::ParameterModes, vec![1102, Value::MAX, 2, 0, 99]);
+
+        assert_eq!(
+            c.run_until_end(),
+            Err(IntcodeError::new(0, Some(1102), ErrorReason::Overflow))
+        );
+    }
+
+    #[test]
+    fn computer_test_relative_base_overflow() {
+        let mut c = create_computer(Level::Day09, vec![109, Value::MAX, 109, 1, 99]);
+
+        assert_eq!(
+            c.run_until_end(),
+            Err(IntcodeError::new(2, Some(109), ErrorReason::Overflow))
+        );
+        assert_eq!(c.get_relative_base(), Value::MAX);
+    }
+
+    #[test]
+    fn computer_test_clone() {
+        // Stores input at address 9 and outputs its double
+        let mut c = create_computer(Level::Day05, vec![3, 9, 102, 2, 9, 10, 4, 10, 99, 0, 0]);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+
+        let mut fork = c.clone();
+        c.push_input(5);
+        fork.push_input(21);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(fork.get(9), Some(0));
+        assert_eq!(fork.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[10]);
+        assert_eq!(fork.get_output(), &[42]);
+        assert_eq!(c.get(9), Some(5));
+        assert_eq!(fork.get(9), Some(21));
+    }
+
+    #[test]
+    fn computer_test_clone_extended_memory() {
+        let mut c = create_computer(Level::Day09, vec![203, 100, 4, 100, 99]);
+        c.push_input(1);
+        assert_eq!(c.step(), Ok(ComputerState::Runnable));
+
+        let mut fork = c.clone();
+        fork.set(100, 2);
+        fork.set(200, 3);
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(fork.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[1]);
+        assert_eq!(fork.get_output(), &[2]);
+        assert_eq!(c.get(200), Some(0));
+        assert_eq!(fork.get(200), Some(3));
+    }
+
+    #[test]
+    fn computer_test_limit() {
+        // Jumps to itself forever
+        let mut c = create_computer(Level::Day05, vec![1105, 1, 0]);
+
+        assert_eq!(c.run_with_limit(10), Ok(ComputerState::Interrupted));
+        assert_eq!(c.get_status(), ComputerState::Interrupted);
+        assert_eq!(c.get_step_count(), 10);
+        assert_eq!(c.step(), Ok(ComputerState::Runnable));
+        assert_eq!(c.run_with_limit(5), Ok(ComputerState::Interrupted));
+        assert_eq!(c.get_step_count(), 16);
+
+        let mut c = create_computer(Level::Day05, vec![1101, 1, 2, 0, 99]);
+        assert_eq!(c.run_with_limit(10), Ok(ComputerState::Finished));
+        assert_eq!(c.get_step_count(), 2);
+
+        let mut c = create_computer(Level::Day05, vec![42]);
+        assert!(c.run_with_limit(10).is_err());
+        assert!(c.run_with_limit(0).is_err());
+    }
+
+    #[test]
+    fn computer_test_deadline() {
+        let mut c = create_computer(Level::Day05, vec![1105, 1, 0]);
+        let deadline = Instant::now() + Duration::from_millis(20);
+
+        assert_eq!(
+            c.run_with_deadline(deadline),
+            Ok(ComputerState::Interrupted)
+        );
+        assert!(Instant::now() >= deadline);
+        assert!(c.get_step_count() > 0);
+
+        let mut c = create_computer(Level::Day05, vec![3, 0, 99]);
+        assert_eq!(
+            c.run_with_deadline(Instant::now() + Duration::from_secs(10)),
+            Ok(ComputerState::WaitingForInput)
+        );
+    }
 }
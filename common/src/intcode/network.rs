@@ -0,0 +1,520 @@
+use crate::intcode::{
+    create_computer, to_address, Computer, ComputerState, IntcodeError, Level, Value,
+};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Address of the NAT. Packets sent to it are kept and resent to address 0 once the network is idle
+pub const NAT_ADDRESS: usize = 255;
+
+/// Value a node reads when its input queue is empty
+pub const NO_PACKET: Value = -1;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum NetworkError {
+    #[error("Node {address}: {error}")]
+    Computer {
+        address: usize,
+        #[source]
+        error: IntcodeError,
+    },
+    #[error("Node {sender} sent packet to unknown address {destination}")]
+    InvalidAddress { sender: usize, destination: Value },
+    #[error("Network is idle and the NAT has no packet")]
+    Deadlock,
+    #[error("All nodes finished")]
+    Halted,
+}
+
+/// Packet of (x, y) from source to destination. Packets sent by the NAT have NAT_ADDRESS as source
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Packet {
+    source: usize,
+    destination: usize,
+    x: Value,
+    y: Value,
+}
+
+impl Packet {
+    pub fn new(source: usize, destination: usize, x: Value, y: Value) -> Self {
+        Self {
+            source,
+            destination,
+            x,
+            y,
+        }
+    }
+
+    pub fn get_source(self) -> usize {
+        self.source
+    }
+
+    pub fn get_destination(self) -> usize {
+        self.destination
+    }
+
+    pub fn get_x(self) -> Value {
+        self.x
+    }
+
+    pub fn get_y(self) -> Value {
+        self.y
+    }
+}
+
+/// Computers exchanging packets, scheduled round-robin.
+///
+/// Every node reads its address on boot. Afterwards it reads x and y of received packets,
+/// or NO_PACKET if there is none, and sends packets by writing destination, x and y.
+/// In every round each node runs until it waits for input. The network is idle after a round
+/// in which every node read NO_PACKET and none sent a packet
+#[derive(Debug, Clone)]
+pub struct Network {
+    nodes: Vec<Computer>,
+    pending: Vec<Vec<Value>>,
+    nat: Option<Packet>,
+    idle: bool,
+}
+
+impl Network {
+    /// Network of size nodes running program
+    pub fn new(level: Level, program: Vec<Value>, size: usize) -> Self {
+        let template = create_computer(level, program);
+        Self::from_computers(vec![template; size])
+    }
+
+    /// Network with a node per computer, addressed by index
+    pub fn from_computers(mut nodes: Vec<Computer>) -> Self {
+        for (address, node) in nodes.iter_mut().enumerate() {
+            node.push_input(address as Value);
+        }
+        Self {
+            pending: vec![Vec::new(); nodes.len()],
+            nodes,
+            nat: None,
+            idle: false,
+        }
+    }
+
+    pub fn get_nodes(&self) -> &[Computer] {
+        &self.nodes
+    }
+
+    /// Last packet the NAT received
+    pub fn get_nat(&self) -> Option<Packet> {
+        self.nat
+    }
+
+    /// Whether the last round left the network idle
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Run every node once and return the packets sent in order.
+    /// If the round left the network idle, the NAT packet is resent to address 0 and returned last
+    pub fn round(&mut self) -> Result<Vec<Packet>, NetworkError> {
+        let mut packets = Vec::new();
+        let mut idle = true;
+
+        for address in 0..self.nodes.len() {
+            let node = &mut self.nodes[address];
+            if node.get_status() == ComputerState::Finished {
+                continue;
+            }
+
+            let polled = node.get_input().is_empty();
+            if polled {
+                node.push_input(NO_PACKET);
+            }
+            node.run_until_end()
+                .map_err(|error| NetworkError::Computer { address, error })?;
+
+            let output = node.take_output();
+            self.pending[address].extend(output);
+            let sent = self.pending[address].len() / 3;
+            for raw in self.pending[address]
+                .drain(..sent * 3)
+                .collect::<Vec<_>>()
+                .chunks(3)
+            {
+                let packet = Packet::new(address, to_destination(address, raw[0])?, raw[1], raw[2]);
+                self.deliver(packet)?;
+                packets.push(packet);
+            }
+
+            idle &= polled && sent == 0;
+        }
+
+        if self
+            .nodes
+            .iter()
+            .all(|node| node.get_status() == ComputerState::Finished)
+        {
+            return Err(NetworkError::Halted);
+        }
+
+        self.idle = idle;
+        if idle {
+            if let Some(packet) = self.nat {
+                let packet = Packet::new(NAT_ADDRESS, 0, packet.x, packet.y);
+                self.deliver(packet)?;
+                packets.push(packet);
+            }
+        }
+
+        Ok(packets)
+    }
+
+    /// Run rounds until stop returns true for a sent packet and return that packet
+    pub fn run_until<F: FnMut(&Packet) -> bool>(
+        &mut self,
+        mut stop: F,
+    ) -> Result<Packet, NetworkError> {
+        loop {
+            for packet in self.round()? {
+                if stop(&packet) {
+                    return Ok(packet);
+                }
+            }
+            if self.idle && self.nat.is_none() {
+                return Err(NetworkError::Deadlock);
+            }
+        }
+    }
+
+    fn deliver(&mut self, packet: Packet) -> Result<(), NetworkError> {
+        if packet.destination == NAT_ADDRESS {
+            self.nat = Some(packet);
+            return Ok(());
+        }
+        match self.nodes.get_mut(packet.destination) {
+            Some(node) => {
+                node.extend_input(vec![packet.x, packet.y]);
+                Ok(())
+            }
+            None => Err(NetworkError::InvalidAddress {
+                sender: packet.source,
+                destination: packet.destination as Value,
+            }),
+        }
+    }
+}
+
+/// Predicate for run_until and run_threaded: true for the first NAT packet whose y
+/// equals the one of the NAT packet before
+pub fn nat_repeats() -> impl FnMut(&Packet) -> bool {
+    let mut last = None;
+    move |packet: &Packet| {
+        if packet.source != NAT_ADDRESS {
+            return false;
+        }
+        let repeated = last == Some(packet.y);
+        last = Some(packet.y);
+        repeated
+    }
+}
+
+fn to_destination(sender: usize, value: Value) -> Result<usize, NetworkError> {
+    to_address(value).map_err(|_| NetworkError::InvalidAddress {
+        sender,
+        destination: value,
+    })
+}
+
+/// Message from the router to a node thread
+enum Input {
+    Packet(Value, Value),
+    Stop,
+}
+
+/// Message from a node thread to the router
+enum Message {
+    Packet(Packet),
+    /// Node read NO_PACKET without sending anything, after having received that many packets
+    Idle(usize, usize),
+    Finished(usize),
+    Error(NetworkError),
+}
+
+/// How long an idle node waits for a packet before reading NO_PACKET again
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// Run size nodes of program, each in its own thread, and route their packets through channels
+/// until stop returns true for a packet. Nodes are idle the same way as in Network.
+///
+/// Packets are routed in the order they arrive, so unlike Network the order between
+/// nodes depends on scheduling. Node threads are stopped and joined before returning
+pub fn run_threaded<F: FnMut(&Packet) -> bool>(
+    level: Level,
+    program: &[Value],
+    size: usize,
+    mut stop: F,
+) -> Result<Packet, NetworkError> {
+    let (outbox, messages) = mpsc::channel();
+    let mut inboxes = Vec::with_capacity(size);
+    let mut handles = Vec::with_capacity(size);
+    let template = create_computer(level, program.to_vec());
+
+    for address in 0..size {
+        let (inbox, receiver) = mpsc::channel();
+        let outbox = outbox.clone();
+        let node = template.clone();
+        inboxes.push(inbox);
+        handles.push(thread::spawn(move || {
+            run_node(address, node, receiver, outbox)
+        }));
+    }
+    drop(outbox);
+
+    let result = route(&inboxes, &messages, &mut stop);
+
+    for inbox in &inboxes {
+        let _ = inbox.send(Input::Stop);
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+    result
+}
+
+fn route<F: FnMut(&Packet) -> bool>(
+    inboxes: &[Sender<Input>],
+    messages: &Receiver<Message>,
+    stop: &mut F,
+) -> Result<Packet, NetworkError> {
+    let size = inboxes.len();
+    let mut delivered = vec![0; size];
+    // Number of received packets at the time a node reported idle
+    let mut idle = vec![None; size];
+    let mut finished = vec![false; size];
+    let mut nat = None;
+
+    let send = |delivered: &mut Vec<usize>, packet: Packet| {
+        if packet.destination == NAT_ADDRESS {
+            return Ok(());
+        }
+        match inboxes.get(packet.destination) {
+            Some(inbox) => {
+                // A finished node does not receive anymore
+                let _ = inbox.send(Input::Packet(packet.x, packet.y));
+                delivered[packet.destination] += 1;
+                Ok(())
+            }
+            None => Err(NetworkError::InvalidAddress {
+                sender: packet.source,
+                destination: packet.destination as Value,
+            }),
+        }
+    };
+
+    loop {
+        match messages.recv().map_err(|_| NetworkError::Halted)? {
+            Message::Packet(packet) => {
+                idle[packet.source] = None;
+                if packet.destination == NAT_ADDRESS {
+                    nat = Some(packet);
+                }
+                send(&mut delivered, packet)?;
+                if stop(&packet) {
+                    return Ok(packet);
+                }
+            }
+            Message::Idle(address, received) => idle[address] = Some(received),
+            Message::Finished(address) => finished[address] = true,
+            Message::Error(error) => return Err(error),
+        }
+
+        if finished.iter().all(|finished| *finished) {
+            return Err(NetworkError::Halted);
+        }
+        let network_idle =
+            (0..size).all(|address| finished[address] || idle[address] == Some(delivered[address]));
+        if network_idle {
+            let packet = nat.ok_or(NetworkError::Deadlock)?;
+            let packet = Packet::new(NAT_ADDRESS, 0, packet.x, packet.y);
+            send(&mut delivered, packet)?;
+            if stop(&packet) {
+                return Ok(packet);
+            }
+        }
+    }
+}
+
+fn run_node(address: usize, mut node: Computer, inbox: Receiver<Input>, outbox: Sender<Message>) {
+    node.push_input(address as Value);
+    let mut pending = Vec::new();
+    let mut received = 0;
+    let mut reported = false;
+
+    loop {
+        loop {
+            let input = if node.get_input().is_empty() && reported {
+                match inbox.recv_timeout(IDLE_WAIT) {
+                    Ok(input) => Ok(input),
+                    Err(RecvTimeoutError::Timeout) => Err(TryRecvError::Empty),
+                    Err(RecvTimeoutError::Disconnected) => Err(TryRecvError::Disconnected),
+                }
+            } else {
+                inbox.try_recv()
+            };
+            match input {
+                Ok(Input::Packet(x, y)) => {
+                    node.extend_input(vec![x, y]);
+                    received += 1;
+                    reported = false;
+                }
+                Ok(Input::Stop) | Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        let polled = node.get_input().is_empty();
+        if polled {
+            node.push_input(NO_PACKET);
+        }
+
+        let state = match node.run_until_end() {
+            Ok(state) => state,
+            Err(error) => {
+                let _ = outbox.send(Message::Error(NetworkError::Computer { address, error }));
+                return;
+            }
+        };
+
+        pending.extend(node.take_output());
+        let sent = pending.len() / 3;
+        for raw in pending.drain(..sent * 3).collect::<Vec<_>>().chunks(3) {
+            let message = to_destination(address, raw[0])
+                .map(|destination| {
+                    Message::Packet(Packet::new(address, destination, raw[1], raw[2]))
+                })
+                .unwrap_or_else(Message::Error);
+            if outbox.send(message).is_err() {
+                return;
+            }
+        }
+
+        if state == ComputerState::Finished {
+            let _ = outbox.send(Message::Finished(address));
+            return;
+        }
+        if sent > 0 {
+            reported = false;
+        } else if polled && !reported {
+            reported = true;
+            if outbox.send(Message::Idle(address, received)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::ErrorReason;
+
+    /// Node 0 sends (0, 100) to node 1 on boot. Every node forwards received packets
+    /// with x incremented to the next address, the last one to the NAT
+    fn forward_program(size: usize) -> Vec<Value> {
+        let source = format!(
+            "
+                    in addr
+                    add addr, #1, dest
+                    eq dest, #{size}, tmp
+                    jz tmp, #start
+                    add #{nat}, #0, dest
+            start:  jnz addr, #loop
+                    out #1
+                    out #0
+                    out #100
+            loop:   in x
+                    eq x, #-1, tmp
+                    jnz tmp, #loop
+                    in y
+                    add x, #1, x
+                    out dest
+                    out x
+                    out y
+                    jz #0, #loop
+            addr:   data 0
+            dest:   data 0
+            x:      data 0
+            y:      data 0
+            tmp:    data 0
+            ",
+            size = size,
+            nat = NAT_ADDRESS
+        );
+        assemble(Level::Day05, &source).expect("Failed assembling")
+    }
+
+    #[test]
+    fn network_round_robin() {
+        let mut network = Network::new(Level::Day05, forward_program(3), 3);
+
+        let first = network.run_until(|packet| packet.get_destination() == NAT_ADDRESS);
+        assert_eq!(first, Ok(Packet::new(2, NAT_ADDRESS, 2, 100)));
+
+        let repeated = network.run_until(nat_repeats());
+        assert_eq!(repeated, Ok(Packet::new(NAT_ADDRESS, 0, 5, 100)));
+        assert_eq!(network.get_nat(), Some(Packet::new(2, NAT_ADDRESS, 5, 100)));
+    }
+
+    #[test]
+    fn network_deterministic() {
+        let mut a = Network::new(Level::Day05, forward_program(4), 4);
+        let mut b = a.clone();
+
+        for _ in 0..10 {
+            assert_eq!(a.round(), b.round());
+        }
+    }
+
+    #[test]
+    fn network_threaded() {
+        let first = run_threaded(Level::Day05, &forward_program(3), 3, |packet| {
+            packet.get_destination() == NAT_ADDRESS
+        });
+        assert_eq!(first, Ok(Packet::new(2, NAT_ADDRESS, 2, 100)));
+
+        let repeated = run_threaded(Level::Day05, &forward_program(3), 3, nat_repeats());
+        assert_eq!(repeated, Ok(Packet::new(NAT_ADDRESS, 0, 5, 100)));
+    }
+
+    #[test]
+    fn network_errors() {
+        // Every node sends to address 7
+        let program = vec![104, 7, 104, 0, 104, 0, 99];
+        assert_eq!(
+            Network::new(Level::Day05, program.clone(), 2).round(),
+            Err(NetworkError::InvalidAddress {
+                sender: 0,
+                destination: 7
+            })
+        );
+        assert!(matches!(
+            run_threaded(Level::Day05, &program, 2, |_| false),
+            Err(NetworkError::InvalidAddress { destination: 7, .. })
+        ));
+
+        // Nodes only read
+        let mut network = Network::new(Level::Day05, vec![3, 5, 1105, 1, 0, 0], 2);
+        assert_eq!(network.run_until(|_| false), Err(NetworkError::Deadlock));
+
+        let mut network = Network::new(Level::Day05, vec![3, 0, 99], 2);
+        assert_eq!(network.round(), Err(NetworkError::Halted));
+
+        let mut network = Network::new(Level::Day05, vec![3, 0, 42], 2);
+        assert_eq!(
+            network.round(),
+            Err(NetworkError::Computer {
+                address: 0,
+                error: IntcodeError::new(2, Some(42), ErrorReason::UnknownOpcode(42))
+            })
+        );
+    }
+}
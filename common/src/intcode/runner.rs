@@ -0,0 +1,175 @@
+use crate::intcode::{Computer, ComputerState, IntcodeError, Value};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use thiserror::Error;
+
+/// Steps a computer runs before it yields to the other tasks of the executor
+const SLICE_STEPS: u64 = 10_000;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum RunError<E> {
+    #[error(transparent)]
+    Computer(#[from] IntcodeError),
+    #[error("Output sink failed")]
+    Sink(#[source] E),
+}
+
+/// Run computer until it finishes, awaiting the next value of input whenever it waits for input
+/// and sending every output value to output. Execution yields to the executor every few thousand
+/// steps, so a busy computer does not stall other tasks.
+///
+/// The input queue of the computer is read before input. An installed input source or output sink
+/// is used as usual and blocks the task, so do not combine them with this runner.
+/// Returns ComputerState::WaitingForInput if input ended while the computer needed more
+pub async fn run<I, O>(
+    computer: &mut Computer,
+    mut input: I,
+    mut output: O,
+) -> Result<ComputerState, RunError<O::Error>>
+where
+    I: Stream<Item = Value> + Unpin,
+    O: Sink<Value> + Unpin,
+{
+    loop {
+        let state = computer.run_with_limit(SLICE_STEPS)?;
+
+        for value in computer.take_output() {
+            output.feed(value).await.map_err(RunError::Sink)?;
+        }
+        output.flush().await.map_err(RunError::Sink)?;
+
+        match state {
+            ComputerState::Interrupted => YieldNow(false).await,
+            ComputerState::WaitingForInput => match input.next().await {
+                Some(value) => computer.push_input(value),
+                None => return Ok(state),
+            },
+            _ => return Ok(state),
+        }
+    }
+}
+
+/// Future that is pending once, after waking its task again
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{create_computer, ErrorReason, Level};
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::future::{join, join_all};
+    use futures::stream;
+    use std::cell::Cell;
+
+    #[test]
+    fn run_stream_to_sink() {
+        // Outputs the sum of every pair of inputs
+        let mut c = create_computer(
+            Level::Day05,
+            vec![3, 13, 3, 14, 1, 13, 14, 15, 4, 15, 1105, 1, 0, 0, 0, 0],
+        );
+        let mut output = Vec::new();
+
+        let state = block_on(run(
+            &mut c,
+            stream::iter(vec![1, 2, 30, 40, 5]),
+            &mut output,
+        ));
+
+        assert_eq!(state, Ok(ComputerState::WaitingForInput));
+        assert_eq!(output, vec![3, 70]);
+        assert_eq!(c.get_ptr(), 2);
+    }
+
+    #[test]
+    fn run_many_on_one_executor() {
+        // Each machine adds one to its input, chained through channels
+        let count = 200;
+        let (first, mut input) = mpsc::unbounded();
+        let mut machines = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (sink, next) = mpsc::unbounded();
+            let mut c = create_computer(Level::Day05, vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+            machines.push(async move { run(&mut c, input, sink).await });
+            input = next;
+        }
+
+        first.unbounded_send(0).unwrap();
+        let results = block_on(join_all(machines));
+
+        assert!(results
+            .iter()
+            .all(|result| *result == Ok(ComputerState::Finished)));
+        assert_eq!(block_on(input.collect::<Vec<_>>()), vec![count as Value]);
+    }
+
+    #[test]
+    fn run_busy_machine_yields() {
+        // Counts down from 100000 before it outputs anything
+        let mut busy = create_computer(
+            Level::Day05,
+            vec![1001, 10, -1, 10, 1005, 10, 0, 4, 10, 99, 100_000],
+        );
+        let mut echo = create_computer(Level::Day05, vec![3, 5, 4, 5, 99, 0]);
+        let busy_done = Cell::new(false);
+
+        let (busy_state, echo_state) = block_on(join(
+            async {
+                let state = run(&mut busy, stream::empty(), Vec::new()).await;
+                busy_done.set(true);
+                state
+            },
+            async {
+                let mut output = Vec::new();
+                let state = run(&mut echo, stream::iter(vec![7]), &mut output).await;
+                // The echo finished while the busy machine was still counting
+                assert!(!busy_done.get());
+                assert_eq!(output, vec![7]);
+                state
+            },
+        ));
+
+        assert_eq!(busy_state, Ok(ComputerState::Finished));
+        assert_eq!(echo_state, Ok(ComputerState::Finished));
+        assert_eq!(busy.get_step_count(), 200_002);
+    }
+
+    #[test]
+    fn run_errors() {
+        let mut c = create_computer(Level::Day05, vec![104, 1, 99]);
+        let (sink, output) = mpsc::unbounded();
+        drop(output);
+
+        assert!(matches!(
+            block_on(run(&mut c, stream::empty(), sink)),
+            Err(RunError::Sink(_))
+        ));
+
+        let mut c = create_computer(Level::Day05, vec![42]);
+        assert_eq!(
+            block_on(run(&mut c, stream::empty(), Vec::new())),
+            Err(RunError::Computer(IntcodeError::new(
+                0,
+                Some(42),
+                ErrorReason::UnknownOpcode(42)
+            )))
+        );
+    }
+}
@@ -0,0 +1,553 @@
+use crate::intcode::trace::{Observer, StepEvent};
+use crate::intcode::{Computer, ComputerState, Value};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+const HEADER: &str = "intcode-session 1";
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Not a session file")]
+    InvalidHeader,
+    #[error("Invalid line {line}: '{text}'")]
+    InvalidLine { line: usize, text: String },
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Io {
+    Input(Value),
+    Output(Value),
+}
+
+impl fmt::Display for Io {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Io::Input(value) => write!(f, "in {}", value),
+            Io::Output(value) => write!(f, "out {}", value),
+        }
+    }
+}
+
+/// Input consumed or output produced by the instruction at pointer in step
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Entry {
+    step: u64,
+    pointer: usize,
+    io: Io,
+}
+
+impl Entry {
+    pub fn new(step: u64, pointer: usize, io: Io) -> Self {
+        Self { step, pointer, io }
+    }
+
+    /// Number of the step, counted from 1 at the start of the recording
+    pub fn get_step(self) -> u64 {
+        self.step
+    }
+
+    pub fn get_pointer(self) -> usize {
+        self.pointer
+    }
+
+    pub fn get_io(self) -> Io {
+        self.io
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.step, self.pointer, self.io)
+    }
+}
+
+/// All I/O of a run, with the number of steps it took and the state it ended in.
+/// Steps that wait for input are not counted, so a replay with all input available matches.
+///
+/// The file format is text: the header `intcode-session 1`, a line `<step> <pointer> in|out <value>`
+/// per entry and a last line `end <steps> <state>`
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Session {
+    entries: Vec<Entry>,
+    steps: u64,
+    state: ComputerState,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            steps: 0,
+            state: ComputerState::Runnable,
+        }
+    }
+}
+
+impl Session {
+    pub fn get_entries(&self) -> &Vec<Entry> {
+        &self.entries
+    }
+
+    /// Consumed input values in order
+    pub fn get_inputs(&self) -> Vec<Value> {
+        self.values(|io| match io {
+            Io::Input(value) => Some(value),
+            Io::Output(_) => None,
+        })
+    }
+
+    /// Produced output values in order
+    pub fn get_outputs(&self) -> Vec<Value> {
+        self.values(|io| match io {
+            Io::Output(value) => Some(value),
+            Io::Input(_) => None,
+        })
+    }
+
+    fn values<F: Fn(Io) -> Option<Value>>(&self, filter: F) -> Vec<Value> {
+        self.entries
+            .iter()
+            .filter_map(|entry| filter(entry.io))
+            .collect()
+    }
+
+    pub fn get_steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// State after the last recorded step
+    pub fn get_state(&self) -> ComputerState {
+        self.state
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for entry in &self.entries {
+            writeln!(writer, "{}", entry)?;
+        }
+        writeln!(writer, "end {} {}", self.steps, state_name(self.state))
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, SessionError> {
+        let mut lines = reader.lines();
+        match lines.next().transpose()? {
+            Some(header) if header.trim() == HEADER => {}
+            _ => return Err(SessionError::InvalidHeader),
+        }
+
+        let mut session = Session::default();
+        let mut ended = false;
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let invalid = || SessionError::InvalidLine {
+                line: index + 2,
+                text: line.clone(),
+            };
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words.is_empty() {
+                continue;
+            }
+            if ended {
+                return Err(invalid());
+            }
+            match words.as_slice() {
+                ["end", steps, state] => {
+                    session.steps = steps.parse().map_err(|_| invalid())?;
+                    session.state = parse_state(state).ok_or_else(invalid)?;
+                    ended = true;
+                }
+                [step, pointer, kind, value] => {
+                    let value = value.parse::<Value>().map_err(|_| invalid())?;
+                    let io = match *kind {
+                        "in" => Io::Input(value),
+                        "out" => Io::Output(value),
+                        _ => return Err(invalid()),
+                    };
+                    session.entries.push(Entry::new(
+                        step.parse().map_err(|_| invalid())?,
+                        pointer.parse().map_err(|_| invalid())?,
+                        io,
+                    ));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        if ended {
+            Ok(session)
+        } else {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Missing end of session").into())
+        }
+    }
+
+    pub fn save<P: AsRef<path::Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<path::Path>>(path: P) -> Result<Self, SessionError> {
+        Self::read_from(io::BufReader::new(fs::File::open(path)?))
+    }
+}
+
+fn state_name(state: ComputerState) -> &'static str {
+    match state {
+        ComputerState::Runnable => "runnable",
+        ComputerState::WaitingForInput => "waiting-for-input",
+        ComputerState::Error => "error",
+        ComputerState::Finished => "finished",
+        ComputerState::Interrupted => "interrupted",
+    }
+}
+
+fn parse_state(name: &str) -> Option<ComputerState> {
+    match name {
+        "runnable" => Some(ComputerState::Runnable),
+        "waiting-for-input" => Some(ComputerState::WaitingForInput),
+        "error" => Some(ComputerState::Error),
+        "finished" => Some(ComputerState::Finished),
+        "interrupted" => Some(ComputerState::Interrupted),
+        _ => None,
+    }
+}
+
+/// Observer that records a session. Steps are counted from its installation
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    session: Session,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn into_session(self) -> Session {
+        self.session
+    }
+}
+
+impl Observer for Recorder {
+    fn on_step(&mut self, event: &StepEvent) {
+        let session = &mut self.session;
+        session.state = event.get_state();
+        if session.state == ComputerState::WaitingForInput {
+            return;
+        }
+        session.steps += 1;
+        for io in step_io(event) {
+            session
+                .entries
+                .push(Entry::new(session.steps, event.get_pointer(), io));
+        }
+    }
+}
+
+fn step_io(event: &StepEvent) -> impl Iterator<Item = Io> + '_ {
+    let input = event.get_input().iter().map(|value| Io::Input(*value));
+    let output = event.get_output().iter().map(|value| Io::Output(*value));
+    input.chain(output)
+}
+
+/// First difference between a replay and its session
+#[derive(Error, Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Divergence {
+    /// I/O of a step differs. Entries are None where nothing happened
+    #[error(
+        "Replay diverged at step {step} (pointer {pointer}): expected {}, got {}",
+        display_entry(.expected),
+        display_entry(.actual)
+    )]
+    Io {
+        step: u64,
+        pointer: usize,
+        expected: Option<Entry>,
+        actual: Option<Entry>,
+    },
+    /// Replay stopped at step in another state or after another number of steps
+    #[error(
+        "Replay diverged at step {step} (pointer {pointer}): expected {} after {expected_steps} steps, got {}",
+        state_name(*.expected_state),
+        state_name(*.state)
+    )]
+    End {
+        step: u64,
+        pointer: usize,
+        expected_steps: u64,
+        expected_state: ComputerState,
+        state: ComputerState,
+    },
+}
+
+fn display_entry(entry: &Option<Entry>) -> String {
+    match entry {
+        Some(entry) => entry.to_string(),
+        None => "nothing".to_string(),
+    }
+}
+
+impl Divergence {
+    /// Step of the replay at which it diverged
+    pub fn get_step(&self) -> u64 {
+        match self {
+            Divergence::Io { step, .. } | Divergence::End { step, .. } => *step,
+        }
+    }
+
+    /// Instruction pointer of that step
+    pub fn get_pointer(&self) -> usize {
+        match self {
+            Divergence::Io { pointer, .. } | Divergence::End { pointer, .. } => *pointer,
+        }
+    }
+}
+
+/// Observer that compares the I/O of every step against a session
+#[derive(Debug)]
+struct Verifier {
+    expected: Vec<Entry>,
+    next: usize,
+    steps: u64,
+    pointer: usize,
+    divergence: Option<Divergence>,
+}
+
+impl Observer for Verifier {
+    fn on_step(&mut self, event: &StepEvent) {
+        self.pointer = event.get_pointer();
+        if event.get_state() == ComputerState::WaitingForInput {
+            return;
+        }
+        self.steps += 1;
+        if self.divergence.is_some() {
+            return;
+        }
+
+        let actual = step_io(event)
+            .map(|io| Entry::new(self.steps, self.pointer, io))
+            .collect::<Vec<_>>();
+        let count = self.expected[self.next..]
+            .iter()
+            .take_while(|entry| entry.step <= self.steps)
+            .count();
+        let expected = &self.expected[self.next..self.next + count];
+        self.next += count;
+
+        if let Some(index) = (0..expected.len().max(actual.len()))
+            .find(|index| expected.get(*index) != actual.get(*index))
+        {
+            self.divergence = Some(Divergence::Io {
+                step: self.steps,
+                pointer: self.pointer,
+                expected: expected.get(index).copied(),
+                actual: actual.get(index).copied(),
+            });
+        }
+    }
+}
+
+/// Run computer with the inputs of session and verify it reproduces the same execution:
+/// the same I/O at the same steps and pointers, the same number of steps and the same end state.
+/// The computer should be in the state the session was recorded from.
+/// Its observer is replaced for the replay and removed afterwards
+pub fn replay(computer: &mut Computer, session: &Session) -> Result<ComputerState, Divergence> {
+    computer.extend_input(session.get_inputs());
+    let verifier = Arc::new(Mutex::new(Verifier {
+        expected: session.entries.clone(),
+        next: 0,
+        steps: 0,
+        pointer: computer.get_ptr(),
+        divergence: None,
+    }));
+    computer.set_observer(Arc::clone(&verifier));
+
+    let running = || {
+        verifier
+            .lock()
+            .is_ok_and(|verifier| verifier.steps < session.steps && verifier.divergence.is_none())
+    };
+    let mut state = computer.get_status();
+    while running() {
+        state = computer.step().unwrap_or(ComputerState::Error);
+        if state != ComputerState::Runnable {
+            break;
+        }
+    }
+    // The wait for input that ended the session is not counted as a step
+    if state == ComputerState::Runnable && session.state == ComputerState::WaitingForInput {
+        state = computer.step().unwrap_or(ComputerState::Error);
+    }
+    computer.remove_observer();
+
+    let verifier = match verifier.lock() {
+        Ok(verifier) => verifier,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if let Some(divergence) = verifier.divergence {
+        return Err(divergence);
+    }
+    if let Some(expected) = verifier.expected.get(verifier.next) {
+        return Err(Divergence::Io {
+            step: verifier.steps,
+            pointer: verifier.pointer,
+            expected: Some(*expected),
+            actual: None,
+        });
+    }
+    if verifier.steps != session.steps || state != session.state {
+        return Err(Divergence::End {
+            step: verifier.steps,
+            pointer: verifier.pointer,
+            expected_steps: session.steps,
+            expected_state: session.state,
+            state,
+        });
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{create_computer, Level};
+
+    /// Echoes input values until it reads 0, then outputs how many it read
+    fn echo() -> Computer {
+        create_computer(
+            Level::Day05,
+            vec![
+                3, 17, 1006, 17, 14, 4, 17, 1001, 18, 1, 18, 1105, 1, 0, 4, 18, 99, 0, 0,
+            ],
+        )
+    }
+
+    fn record(mut computer: Computer, input: &[Value]) -> Session {
+        let recorder = Arc::new(Mutex::new(Recorder::new()));
+        computer.set_observer(Arc::clone(&recorder));
+        computer.extend_input(input.iter().copied());
+        computer.run_until_end().unwrap();
+        computer.remove_observer();
+        let session = recorder.lock().unwrap().get_session().clone();
+        session
+    }
+
+    #[test]
+    fn record_session() {
+        let session = record(echo(), &[5, 7, 0]);
+
+        assert_eq!(session.get_inputs(), vec![5, 7, 0]);
+        assert_eq!(session.get_outputs(), vec![5, 7, 2]);
+        assert_eq!(session.get_state(), ComputerState::Finished);
+        assert_eq!(session.get_steps(), 14);
+        assert_eq!(session.get_entries()[1], Entry::new(3, 5, Io::Output(5)));
+
+        let mut buffer = Vec::new();
+        session.write_to(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert!(text.starts_with("intcode-session 1\n1 0 in 5\n3 5 out 5\n"));
+        assert!(text.ends_with("13 14 out 2\nend 14 finished\n"));
+        assert_eq!(Session::read_from(buffer.as_slice()).unwrap(), session);
+
+        assert!(matches!(
+            Session::read_from(&b"session\n"[..]),
+            Err(SessionError::InvalidHeader)
+        ));
+        assert_eq!(
+            Session::read_from(&b"intcode-session 1\n1 0 in x\n"[..])
+                .unwrap_err()
+                .to_string(),
+            "Invalid line 2: '1 0 in x'"
+        );
+        assert!(matches!(
+            Session::read_from(&b"intcode-session 1\n1 0 in 5\n"[..]),
+            Err(SessionError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn replay_reproduces() {
+        let session = record(echo(), &[5, 7, 0]);
+        let mut c = echo();
+
+        assert_eq!(replay(&mut c, &session), Ok(ComputerState::Finished));
+        assert_eq!(c.take_output(), vec![5, 7, 2]);
+        assert_eq!(c.get_step_count(), 14);
+    }
+
+    #[test]
+    fn replay_interactive() {
+        let recorder = Arc::new(Mutex::new(Recorder::new()));
+        let mut c = echo();
+        c.set_observer(Arc::clone(&recorder));
+        for value in &[3, 4] {
+            assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+            c.push_input(*value);
+        }
+        assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+        let session = recorder.lock().unwrap().get_session().clone();
+        assert_eq!(session.get_steps(), 10);
+        assert_eq!(session.get_state(), ComputerState::WaitingForInput);
+
+        assert_eq!(
+            replay(&mut echo(), &session),
+            Ok(ComputerState::WaitingForInput)
+        );
+    }
+
+    #[test]
+    fn replay_divergence() {
+        let session = record(echo(), &[5, 7, 0]);
+
+        // Counter starts at 1 instead of 0
+        let mut c = echo();
+        c.set(18, 1);
+        let divergence = replay(&mut c, &session).unwrap_err();
+        assert_eq!(
+            divergence,
+            Divergence::Io {
+                step: 13,
+                pointer: 14,
+                expected: Some(Entry::new(13, 14, Io::Output(2))),
+                actual: Some(Entry::new(13, 14, Io::Output(3))),
+            }
+        );
+        assert_eq!(
+            divergence.to_string(),
+            "Replay diverged at step 13 (pointer 14): expected 13 14 out 2, got 13 14 out 3"
+        );
+
+        // Outputs the address of the value instead of the value
+        let mut c = echo();
+        c.set(5, 104);
+        let divergence = replay(&mut c, &session).unwrap_err();
+        assert_eq!((divergence.get_step(), divergence.get_pointer()), (3, 5));
+
+        // Input ends early, so the replay waits where the session went on
+        let mut short = session.clone();
+        short.entries.retain(|entry| entry.io != Io::Input(0));
+        let divergence = replay(&mut echo(), &short).unwrap_err();
+        assert_eq!(
+            divergence.to_string(),
+            "Replay diverged at step 10 (pointer 0): expected 13 14 out 2, got nothing"
+        );
+
+        // Replay runs past the end of the session
+        let mut long = session;
+        long.steps = 20;
+        assert_eq!(
+            replay(&mut echo(), &long).unwrap_err().to_string(),
+            "Replay diverged at step 14 (pointer 16): expected finished after 20 steps, got finished"
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use crate::intcode::varint::{
+    read_address, read_signed, read_unsigned, read_values, write_signed, write_unsigned,
+    write_values, VarintError,
+};
+use crate::intcode::{
+    ComputerState, ErrorReason, IntcodeError, Level, NegativeAddressError, Value,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path;
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"ICSN";
+const VERSION: u8 = 3;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Not a snapshot file")]
+    InvalidMagic,
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Unknown level {0}")]
+    InvalidLevel(u8),
+    #[error("Unknown state {0}")]
+    InvalidState(u8),
+    #[error("Unknown error reason {0}")]
+    InvalidReason(u8),
+    #[error("Number does not fit into 64 bits")]
+    InvalidNumber,
+}
+
+impl From<VarintError> for SnapshotError {
+    fn from(err: VarintError) -> Self {
+        match err {
+            VarintError::Io(err) => SnapshotError::Io(err),
+            VarintError::Overflow => SnapshotError::InvalidNumber,
+        }
+    }
+}
+
+/// State of a computer without its instructions, I/O sources, sinks and observer.
+/// Restoring binds the instructions of the stored level again.
+///
+/// The file format is the magic `ICSN`, a version byte, level and state as bytes, followed by
+/// pointer, relative base, step count, main memory, extended memory, input queue, output buffer and error.
+/// All numbers are LEB128 varints, signed ones zigzag encoded, lists are prefixed by their length.
+/// Optional values are prefixed by a byte that is 1 if they are present.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    pub(crate) level: Level,
+    pub(crate) state: ComputerState,
+    pub(crate) instruction_pointer: usize,
+    pub(crate) relative_base: Value,
+    pub(crate) steps: u64,
+    pub(crate) main_memory: Vec<Value>,
+    pub(crate) extended_memory: BTreeMap<usize, Value>,
+    pub(crate) input: Vec<Value>,
+    pub(crate) output: Vec<Value>,
+    pub(crate) error: Option<IntcodeError>,
+}
+
+impl Snapshot {
+    pub fn get_level(&self) -> Level {
+        self.level
+    }
+
+    pub fn get_state(&self) -> ComputerState {
+        self.state
+    }
+
+    /// Number of steps the computer executed, see Computer::get_step_count
+    pub fn get_step_count(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn get_error(&self) -> Option<&IntcodeError> {
+        self.error.as_ref()
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, self.level as u8, state_to_byte(self.state)])?;
+
+        let mut buffer = Vec::new();
+        write_unsigned(&mut buffer, self.instruction_pointer as u64);
+        write_signed(&mut buffer, self.relative_base);
+        write_unsigned(&mut buffer, self.steps);
+        write_values(&mut buffer, &self.main_memory);
+        write_unsigned(&mut buffer, self.extended_memory.len() as u64);
+        for (address, value) in &self.extended_memory {
+            write_unsigned(&mut buffer, *address as u64);
+            write_signed(&mut buffer, *value);
+        }
+        write_values(&mut buffer, &self.input);
+        write_values(&mut buffer, &self.output);
+        write_error(&mut buffer, self.error.as_ref());
+
+        writer.write_all(&buffer)
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, SnapshotError> {
+        let mut header = [0; 7];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        if header[4] != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[4]));
+        }
+        let level = level_from_byte(header[5]).ok_or(SnapshotError::InvalidLevel(header[5]))?;
+        let state = state_from_byte(header[6]).ok_or(SnapshotError::InvalidState(header[6]))?;
+
+        let instruction_pointer = read_address(&mut reader)?;
+        let relative_base = read_signed(&mut reader)?;
+        let steps = read_unsigned(&mut reader)?;
+        let main_memory = read_values(&mut reader)?;
+        let mut extended_memory = BTreeMap::new();
+        for _ in 0..read_unsigned(&mut reader)? {
+            let address = read_address(&mut reader)?;
+            extended_memory.insert(address, read_signed(&mut reader)?);
+        }
+        let input = read_values(&mut reader)?;
+        let output = read_values(&mut reader)?;
+        let error = read_error(&mut reader)?;
+
+        Ok(Self {
+            level,
+            state,
+            instruction_pointer,
+            relative_base,
+            steps,
+            main_memory,
+            extended_memory,
+            input,
+            output,
+            error,
+        })
+    }
+
+    pub fn save<P: AsRef<path::Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<path::Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::read_from(io::BufReader::new(fs::File::open(path)?))
+    }
+}
+
+fn write_error(buffer: &mut Vec<u8>, error: Option<&IntcodeError>) {
+    let error = match error {
+        Some(error) => error,
+        None => {
+            buffer.push(0);
+            return;
+        }
+    };
+    buffer.push(1);
+    write_unsigned(buffer, error.get_address() as u64);
+    match error.get_opcode() {
+        Some(opcode) => {
+            buffer.push(1);
+            write_signed(buffer, opcode);
+        }
+        None => buffer.push(0),
+    }
+    match *error.get_reason() {
+        ErrorReason::NoInstruction => buffer.push(0),
+        ErrorReason::UnknownOpcode(opcode) => {
+            buffer.push(1);
+            write_signed(buffer, opcode);
+        }
+        ErrorReason::UnknownMode(index) => {
+            buffer.push(2);
+            write_unsigned(buffer, index as u64);
+        }
+        ErrorReason::InvalidRead { address, index } => {
+            buffer.push(3);
+            write_unsigned(buffer, address as u64);
+            write_unsigned(buffer, index as u64);
+        }
+        ErrorReason::ImmediateAddress(index) => {
+            buffer.push(4);
+            write_unsigned(buffer, index as u64);
+        }
+        ErrorReason::NegativeAddress(NegativeAddressError(value)) => {
+            buffer.push(5);
+            write_signed(buffer, value);
+        }
+        ErrorReason::InvalidPointer(address) => {
+            buffer.push(6);
+            write_unsigned(buffer, address as u64);
+        }
+        ErrorReason::Overflow => buffer.push(7),
+    }
+}
+
+fn read_error<R: Read>(reader: &mut R) -> Result<Option<IntcodeError>, SnapshotError> {
+    if read_byte(reader)? == 0 {
+        return Ok(None);
+    }
+    let address = read_address(reader)?;
+    let opcode = match read_byte(reader)? {
+        0 => None,
+        _ => Some(read_signed(reader)?),
+    };
+    let reason = match read_byte(reader)? {
+        0 => ErrorReason::NoInstruction,
+        1 => ErrorReason::UnknownOpcode(read_signed(reader)?),
+        2 => ErrorReason::UnknownMode(read_address(reader)?),
+        3 => ErrorReason::InvalidRead {
+            address: read_address(reader)?,
+            index: read_address(reader)?,
+        },
+        4 => ErrorReason::ImmediateAddress(read_address(reader)?),
+        5 => ErrorReason::NegativeAddress(NegativeAddressError(read_signed(reader)?)),
+        6 => ErrorReason::InvalidPointer(read_address(reader)?),
+        7 => ErrorReason::Overflow,
+        byte => return Err(SnapshotError::InvalidReason(byte)),
+    };
+    Ok(Some(IntcodeError::new(address, opcode, reason)))
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn state_to_byte(state: ComputerState) -> u8 {
+    match state {
+        ComputerState::Runnable => 0,
+        ComputerState::WaitingForInput => 1,
+        ComputerState::Error => 2,
+        ComputerState::Finished => 3,
+        ComputerState::Interrupted => 4,
+    }
+}
+
+fn state_from_byte(byte: u8) -> Option<ComputerState> {
+    match byte {
+        0 => Some(ComputerState::Runnable),
+        1 => Some(ComputerState::WaitingForInput),
+        2 => Some(ComputerState::Error),
+        3 => Some(ComputerState::Finished),
+        4 => Some(ComputerState::Interrupted),
+        _ => None,
+    }
+}
+
+fn level_from_byte(byte: u8) -> Option<Level> {
+    [
+        Level::None,
+        Level::Day02,
+        Level::ParameterModes,
+        Level::InputOutput,
+        Level::Day05,
+        Level::Day09,
+    ]
+    .iter()
+    .copied()
+    .find(|level| *level as u8 == byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{create_computer, Computer};
+
+    #[test]
+    fn snapshot_round_trip() {
+        // Reads two inputs into extended memory and outputs their sum
+        let mut c = create_computer(
+            Level::Day09,
+            vec![203, 100, 203, 101, 1, 100, 101, 102, 4, 102, 99],
+        );
+        c.push_input(-40);
+        assert_eq!(c.step(), Ok(ComputerState::Runnable));
+
+        let snapshot = c.snapshot();
+        let mut buffer = Vec::new();
+        snapshot.write_to(&mut buffer).unwrap();
+        let loaded = Snapshot::read_from(buffer.as_slice()).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        let mut restored = Computer::from_snapshot(&loaded);
+        assert_eq!(restored.get_ptr(), 2);
+        assert_eq!(restored.get_step_count(), 1);
+        assert_eq!(restored.get(100), Some(-40));
+
+        // Both machines continue independently
+        c.push_input(2);
+        restored.push_input(50);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(restored.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(c.get_output(), &[-38]);
+        assert_eq!(restored.get_output(), &[10]);
+        assert_eq!(restored.get_step_count(), c.get_step_count());
+    }
+
+    #[test]
+    fn snapshot_waiting_for_input() {
+        let mut c = create_computer(Level::Day05, vec![4, 5, 3, 5, 99, 7]);
+        assert_eq!(c.run_until_end(), Ok(ComputerState::WaitingForInput));
+
+        let mut buffer = Vec::new();
+        c.snapshot().write_to(&mut buffer).unwrap();
+        let mut restored =
+            Computer::from_snapshot(&Snapshot::read_from(buffer.as_slice()).unwrap());
+
+        assert_eq!(restored.get_status(), ComputerState::WaitingForInput);
+        assert_eq!(restored.get_output(), &[7]);
+        restored.push_input(8);
+        assert_eq!(restored.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(restored.get(5), Some(8));
+    }
+
+    #[test]
+    fn snapshot_error() {
+        let mut c = create_computer(Level::Day05, vec![1, -1, 0, 0, 99]);
+        let error = c.run_until_end().unwrap_err();
+
+        let mut buffer = Vec::new();
+        c.snapshot().write_to(&mut buffer).unwrap();
+        let loaded = Snapshot::read_from(buffer.as_slice()).unwrap();
+        assert_eq!(loaded.get_error(), Some(&error));
+
+        let mut restored = Computer::from_snapshot(&loaded);
+        assert_eq!(restored.get_status(), ComputerState::Error);
+        assert_eq!(restored.get_error(), Some(&error));
+        assert_eq!(restored.run_until_end(), Err(error));
+    }
+
+    #[test]
+    fn snapshot_invalid() {
+        assert!(matches!(
+            Snapshot::read_from(&b"ICSX\x03\x05\x00"[..]),
+            Err(SnapshotError::InvalidMagic)
+        ));
+        assert!(matches!(
+            Snapshot::read_from(&b"ICSN\x03\x2a\x00"[..]),
+            Err(SnapshotError::InvalidLevel(42))
+        ));
+        assert!(matches!(
+            Snapshot::read_from(&b"ICSN\x03\x05\x00\x00"[..]),
+            Err(SnapshotError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::intcode::{ComputerState, ErrorReason, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Receives every instruction a computer executes
+pub trait Observer: Send {
+    fn on_step(&mut self, event: &StepEvent);
+}
+
+impl fmt::Debug for dyn Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observer")
+    }
+}
+
+impl<F: FnMut(&StepEvent) + Send> Observer for F {
+    fn on_step(&mut self, event: &StepEvent) {
+        self(event)
+    }
+}
+
+/// Shared observer, so it can still be read while installed on a computer.
+/// A poisoned lock is used anyway, the observer only collects data
+impl<O: Observer> Observer for Arc<Mutex<O>> {
+    fn on_step(&mut self, event: &StepEvent) {
+        match self.lock() {
+            Ok(mut observer) => observer.on_step(event),
+            Err(poisoned) => poisoned.into_inner().on_step(event),
+        }
+    }
+}
+
+/// Single executed instruction
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct StepEvent {
+    pointer: usize,
+    opcode: Option<Value>,
+    mnemonic: Option<&'static str>,
+    operands: Vec<Value>,
+    writes: Vec<(usize, Value)>,
+    input: Vec<Value>,
+    output: Vec<Value>,
+    state: ComputerState,
+    error: Option<ErrorReason>,
+}
+
+impl StepEvent {
+    pub fn new(
+        pointer: usize,
+        opcode: Option<Value>,
+        mnemonic: Option<&'static str>,
+        operands: Vec<Value>,
+    ) -> Self {
+        Self {
+            pointer,
+            opcode,
+            mnemonic,
+            operands,
+            writes: Vec::new(),
+            input: Vec::new(),
+            output: Vec::new(),
+            state: ComputerState::Runnable,
+            error: None,
+        }
+    }
+
+    /// Complete event with the effects of executing the instruction
+    pub fn finish(
+        &mut self,
+        writes: Vec<(usize, Value)>,
+        input: Vec<Value>,
+        output: Vec<Value>,
+        state: ComputerState,
+        error: Option<ErrorReason>,
+    ) {
+        self.writes = writes;
+        self.input = input;
+        self.output = output;
+        self.state = state;
+        self.error = error;
+    }
+
+    /// Address of the executed instruction
+    pub fn get_pointer(&self) -> usize {
+        self.pointer
+    }
+
+    /// Raw opcode. None if there was no memory at the pointer
+    pub fn get_opcode(&self) -> Option<Value> {
+        self.opcode
+    }
+
+    /// Mnemonic of the instruction. None if the opcode is unknown
+    pub fn get_mnemonic(&self) -> Option<&'static str> {
+        self.mnemonic
+    }
+
+    /// Raw operand words as they were in memory before execution
+    pub fn get_operands(&self) -> &Vec<Value> {
+        &self.operands
+    }
+
+    /// Memory writes as (address, value) in the order they happened
+    pub fn get_writes(&self) -> &Vec<(usize, Value)> {
+        &self.writes
+    }
+
+    /// Input values the instruction consumed
+    pub fn get_input(&self) -> &Vec<Value> {
+        &self.input
+    }
+
+    /// Output values the instruction produced, whether buffered or written to a sink
+    pub fn get_output(&self) -> &Vec<Value> {
+        &self.output
+    }
+
+    /// State of the computer after the instruction
+    pub fn get_state(&self) -> ComputerState {
+        self.state
+    }
+
+    pub fn get_error(&self) -> Option<ErrorReason> {
+        self.error
+    }
+}
+
+impl fmt::Display for StepEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5}: ", self.pointer)?;
+        match (self.mnemonic, self.opcode) {
+            (Some(mnemonic), _) => write!(f, "{}", mnemonic)?,
+            (None, Some(opcode)) => write!(f, "<{}>", opcode)?,
+            (None, None) => write!(f, "<->")?,
+        }
+        for operand in &self.operands {
+            write!(f, " {}", operand)?;
+        }
+        for (address, value) in &self.writes {
+            write!(f, " [{}]={}", address, value)?;
+        }
+        for value in &self.input {
+            write!(f, " <{}", value)?;
+        }
+        for value in &self.output {
+            write!(f, " >{}", value)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " ! {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes every executed instruction as a line
+#[derive(Debug)]
+pub struct TraceWriter<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write + Send> Observer for TraceWriter<W> {
+    fn on_step(&mut self, event: &StepEvent) {
+        // A broken trace must not stop the computer
+        let _ = writeln!(self.writer, "{}", event);
+    }
+}
+
+/// Counts how often every instruction was executed, by mnemonic
+#[derive(Debug, Clone, Default)]
+pub struct InstructionCounter {
+    counts: HashMap<&'static str, usize>,
+}
+
+impl InstructionCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_counts(&self) -> &HashMap<&'static str, usize> {
+        &self.counts
+    }
+
+    pub fn get_count(&self, mnemonic: &str) -> usize {
+        self.counts.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    pub fn get_total(&self) -> usize {
+        self.counts.values().sum()
+    }
+}
+
+impl Observer for InstructionCounter {
+    fn on_step(&mut self, event: &StepEvent) {
+        if let Some(mnemonic) = event.mnemonic {
+            *self.counts.entry(mnemonic).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Collects addresses of executed instructions
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    addresses: BTreeSet<usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_addresses(&self) -> &BTreeSet<usize> {
+        &self.addresses
+    }
+
+    pub fn is_covered(&self, address: usize) -> bool {
+        self.addresses.contains(&address)
+    }
+}
+
+impl Observer for Coverage {
+    fn on_step(&mut self, event: &StepEvent) {
+        self.addresses.insert(event.pointer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{create_computer, Level};
+
+    #[test]
+    fn trace_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+
+        let mut c = create_computer(Level::Day02, vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        c.set_observer(move |event: &StepEvent| sink.lock().unwrap().push(event.clone()));
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].get_pointer(), 0);
+        assert_eq!(events[0].get_mnemonic(), Some("add"));
+        assert_eq!(events[0].get_operands(), &vec![9, 10, 3]);
+        assert_eq!(events[0].get_writes(), &vec![(3, 70)]);
+        assert_eq!(events[1].get_writes(), &vec![(0, 3500)]);
+        assert_eq!(events[2].get_state(), ComputerState::Finished);
+        assert_eq!(events[0].to_string(), "    0: add 9 10 3 [3]=70");
+    }
+
+    #[test]
+    fn trace_error() {
+        let mut c = create_computer(Level::Day02, vec![1, 0, 0, 0, 42]);
+        let writer = Arc::new(Mutex::new(TraceWriter::new(Vec::new())));
+        c.set_observer(writer.clone());
+
+        assert!(c.run_until_end().is_err());
+        c.remove_observer();
+
+        let writer = Arc::try_unwrap(writer)
+            .unwrap()
+            .into_inner()
+            .unwrap()
+            .into_inner();
+        let trace = String::from_utf8(writer).unwrap();
+        assert_eq!(
+            trace,
+            "    0: add 0 0 0 [0]=2\n    4: <42> ! Unknown opcode 42\n"
+        );
+    }
+
+    #[test]
+    fn count_and_cover() {
+        let counter = Arc::new(Mutex::new(InstructionCounter::new()));
+        let coverage = Arc::new(Mutex::new(Coverage::new()));
+        let observers = (counter.clone(), coverage.clone());
+
+        // Count down from 3 in address 14, address 13 is never executed
+        let mut c = create_computer(
+            Level::Day05,
+            vec![1101, 0, 3, 14, 1001, 14, -1, 14, 1005, 14, 4, 99, 0, 99, 0],
+        );
+        c.set_observer(move |event: &StepEvent| {
+            observers.0.lock().unwrap().on_step(event);
+            observers.1.lock().unwrap().on_step(event);
+        });
+
+        assert_eq!(c.run_until_end(), Ok(ComputerState::Finished));
+        assert_eq!(counter.lock().unwrap().get_count("add"), 4);
+        assert_eq!(counter.lock().unwrap().get_count("jnz"), 3);
+        assert_eq!(counter.lock().unwrap().get_total(), 8);
+        assert!(coverage.lock().unwrap().is_covered(8));
+        assert!(!coverage.lock().unwrap().is_covered(13));
+        assert_eq!(
+            coverage
+                .lock()
+                .unwrap()
+                .get_addresses()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![&0, &4, &8, &11]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::intcode::Value;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+/// LEB128 varints, signed ones zigzag encoded, as used by snapshots and compact programs
+#[derive(Debug)]
+pub(crate) enum VarintError {
+    Io(io::Error),
+    /// Number does not fit into 64 bits
+    Overflow,
+}
+
+impl From<io::Error> for VarintError {
+    fn from(err: io::Error) -> Self {
+        VarintError::Io(err)
+    }
+}
+
+pub(crate) fn write_unsigned(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+pub(crate) fn write_signed(buffer: &mut Vec<u8>, value: Value) {
+    write_unsigned(buffer, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+pub(crate) fn write_values(buffer: &mut Vec<u8>, values: &[Value]) {
+    write_unsigned(buffer, values.len() as u64);
+    for value in values {
+        write_signed(buffer, *value);
+    }
+}
+
+pub(crate) fn read_unsigned<R: Read>(reader: &mut R) -> Result<u64, VarintError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        let bits = u64::from(byte[0] & 0x7f);
+        if shift == 63 && bits > 1 {
+            return Err(VarintError::Overflow);
+        }
+        value |= bits << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(VarintError::Overflow)
+}
+
+pub(crate) fn read_signed<R: Read>(reader: &mut R) -> Result<Value, VarintError> {
+    let value = read_unsigned(reader)?;
+    Ok((value >> 1) as Value ^ -((value & 1) as Value))
+}
+
+pub(crate) fn read_address<R: Read>(reader: &mut R) -> Result<usize, VarintError> {
+    usize::try_from(read_unsigned(reader)?).map_err(|_| VarintError::Overflow)
+}
+
+pub(crate) fn read_values<R: Read>(reader: &mut R) -> Result<Vec<Value>, VarintError> {
+    let len = read_unsigned(reader)?;
+    // Length comes from the file, so do not trust it for preallocation
+    let mut values = Vec::with_capacity(len.min(1 << 16) as usize);
+    for _ in 0..len {
+        values.push(read_signed(reader)?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        for value in &[0, 1, -1, 63, -64, 64, 1_000_000, Value::MAX, Value::MIN] {
+            let mut buffer = Vec::new();
+            write_signed(&mut buffer, *value);
+            assert_eq!(read_signed(&mut buffer.as_slice()).unwrap(), *value);
+        }
+    }
+}
@@ -43,7 +43,7 @@ impl Point {
     }
 
     pub fn manhatten_distance(self, other: Point) -> usize {
-        (self.x - other.x).abs() as usize + (self.y - other.y).abs() as usize
+        (self.x - other.x).unsigned_abs() as usize + (self.y - other.y).unsigned_abs() as usize
     }
 }
 
@@ -16,7 +16,7 @@ impl Panel {
         Self { wires }
     }
 
-    pub fn get_crosses(&self) -> CrossedPoints {
+    pub fn get_crosses(&self) -> CrossedPoints<'_> {
         let crosses = self.wires[0].get_crosses(&self.wires[1]);
         CrossedPoints::new(self, crosses)
     }
@@ -63,7 +63,7 @@ impl<'a> CrossedPoints<'a> {
             .iter()
             .map(|p| (p, p.manhatten_distance(Default::default())))
             .collect::<Vec<(&Point, usize)>>();
-        temp.sort_by(|(_, d), (_, d2)| d.cmp(d2));
+        temp.sort_by_key(|(_, d)| *d);
         temp[0]
     }
 
@@ -79,7 +79,7 @@ impl<'a> CrossedPoints<'a> {
                 )
             })
             .collect::<Vec<(&Point, usize)>>();
-        temp.sort_by(|(_, d), (_, d2)| d.cmp(d2));
+        temp.sort_by_key(|(_, d)| *d);
         temp[0]
     }
 
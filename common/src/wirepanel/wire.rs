@@ -30,7 +30,7 @@ impl Wire {
         for this_point in self.get_points() {
             for other_point in other.get_points() {
                 if this_point == other_point {
-                    points.push(this_point.clone());
+                    points.push(*this_point);
                 }
             }
         }
@@ -39,10 +39,10 @@ impl Wire {
     }
 
     pub fn get_length_to_point(&self, point: &Point) -> Option<usize> {
-        match self.points.iter().position(|p| p == point) {
-            Some(val) => Some(val + 1),
-            None => None,
-        }
+        self.points
+            .iter()
+            .position(|p| p == point)
+            .map(|val| val + 1)
     }
 }
 
@@ -1,5 +1,7 @@
-use common::intcode::{create_computer, ComputerState, Level};
-use std::{fs, io, path};
+use common::intcode::loader;
+use common::intcode::{create_computer, ComputerState, Level, Value};
+use rayon::prelude::*;
+use std::{io, path};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -8,36 +10,86 @@ struct Settings {
     #[structopt(parse(from_os_str))]
     /// Path to file containing the initial memory
     input: path::PathBuf,
+    #[structopt(short, long, default_value = "12")]
+    /// Value written to address 1
+    noun: Value,
+    #[structopt(short, long, default_value = "2")]
+    /// Value written to address 2
+    verb: Value,
+    #[structopt(short, long)]
+    /// Search noun and verb in 0..=99 that produce target, instead of running with noun and verb
+    search: Option<Value>,
 }
 
 fn main() -> io::Result<()> {
     let settings = Settings::from_args();
 
-    let mut memory = get_initial_memory_from_file(settings.input)?;
-    memory[1] = 12;
-    memory[2] = 2;
+    let memory = get_initial_memory_from_file(settings.input)?;
+    if memory.len() < 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Program has no addresses 1 and 2 for noun and verb",
+        ));
+    }
 
-    let mut computer = create_computer(Level::Day02, memory);
+    if let Some(target) = settings.search {
+        match search(&memory, target) {
+            Some((noun, verb)) => println!(
+                "Found noun {} and verb {}: {}",
+                noun,
+                verb,
+                100 * noun + verb
+            ),
+            None => println!("No noun and verb produce {}", target),
+        }
+        return Ok(());
+    }
 
-    if computer.run_until_end() == ComputerState::Finished {
-        println!(
+    let mut computer = create_computer(
+        Level::Day02,
+        with_noun_and_verb(&memory, settings.noun, settings.verb),
+    );
+
+    match computer.run_until_end() {
+        Ok(ComputerState::Finished) => println!(
             "Computer finished with: {}",
             computer
                 .get(0)
                 .expect("Could not get output memory of computer")
-        );
-    } else {
-        println!("Computer threw error");
+        ),
+        Ok(state) => println!("Computer stopped with state: {:?}", state),
+        Err(err) => println!("Computer threw error: {}", err),
     }
 
     Ok(())
 }
 
-fn get_initial_memory_from_file<P: AsRef<path::Path>>(file: P) -> io::Result<Vec<usize>> {
-    let res = fs::read_to_string(file)?
-        .split(',')
-        .map(str::parse::<usize>)
-        .filter_map(Result::ok)
-        .collect();
-    Ok(res)
+/// Run every combination of noun and verb in parallel and return the first one,
+/// ordered by noun then verb, that leaves target at address 0
+fn search(memory: &[Value], target: Value) -> Option<(Value, Value)> {
+    (0..=99)
+        .into_par_iter()
+        .flat_map(|noun| (0..=99).into_par_iter().map(move |verb| (noun, verb)))
+        .find_first(|(noun, verb)| run(with_noun_and_verb(memory, *noun, *verb)) == Some(target))
+}
+
+/// Value at address 0 after the computer finished. None if it did not finish
+fn run(memory: Vec<Value>) -> Option<Value> {
+    let mut computer = create_computer(Level::Day02, memory);
+    match computer.run_until_end() {
+        Ok(ComputerState::Finished) => computer.get(0),
+        _ => None,
+    }
+}
+
+/// Copy of memory with noun at address 1 and verb at address 2. Memory must have at least 3 cells
+fn with_noun_and_verb(memory: &[Value], noun: Value, verb: Value) -> Vec<Value> {
+    let mut memory = memory.to_vec();
+    memory[1] = noun;
+    memory[2] = verb;
+    memory
+}
+
+fn get_initial_memory_from_file<P: AsRef<path::Path>>(file: P) -> io::Result<Vec<Value>> {
+    loader::load(file).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
 }
@@ -29,6 +29,6 @@ fn main() -> io::Result<()> {
 fn get_panel<P: AsRef<path::Path>>(file: P) -> io::Result<Panel> {
     match fs::read_to_string(file)?.parse::<Panel>() {
         Ok(panel) => Ok(panel),
-        Err(err) => panic!(err),
+        Err(err) => panic!("{}", err),
     }
 }
@@ -0,0 +1,426 @@
+use common::intcode::cfg::analyze_with;
+use common::intcode::decompiler::decompile_with;
+use common::intcode::disassembler::disassemble_computer;
+use common::intcode::loader;
+use common::intcode::snapshot::Snapshot;
+use common::intcode::{create_computer, Computer, ComputerState, Level, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
+use std::path;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "debugger", about = "Interactive debugger for Intcode programs")]
+struct Settings {
+    #[structopt(parse(from_os_str))]
+    /// Path to file containing the program
+    input: path::PathBuf,
+    #[structopt(short, long, default_value = "day09")]
+    /// Level of the computer (none, day02, parameter-modes, input-output, day05, day09)
+    level: Level,
+}
+
+const HELP: &str = "\
+Commands:
+  s, step [n]            execute n instructions (default 1)
+  c, continue            run until breakpoint, watchpoint, input wait or end
+  rs, rstep [n]          undo n instructions (default 1)
+  rc, rcontinue          run backwards until breakpoint, watchpoint or start of history
+  lw, lastwrite <addr>   run backwards to the last instruction that wrote to address
+  b, break <addr>        set breakpoint at address
+  d, delete <addr>       remove breakpoint at address
+  w, watch <addr>        stop when the value at address changes
+  u, unwatch <addr>      remove watchpoint at address
+  p, print <addr> [n]    print n values starting at address (default 1)
+  set <addr> <value>     write value to address
+  l, list [addr] [n]     disassemble n lines starting at address (default pointer)
+  i, info                show instruction pointer, relative base, state, break- and watchpoints
+  is, instructions       list code, mnemonic and arity of every instruction
+  cfg [dot]              show the control flow graph of the program, as Graphviz DOT with dot
+  dc, decompile          show the program as pseudocode
+  in, input <values..>   push values to the input queue
+  r, reset               reload the program
+  save <path>            write a snapshot of the computer to path
+  load <path>            restore the computer from a snapshot at path
+  h, help                show this help
+  q, quit                exit the debugger";
+
+/// Steps that can be undone
+const HISTORY_STEPS: usize = 1_000_000;
+
+struct Debugger {
+    program: Vec<Value>,
+    level: Level,
+    computer: Computer,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, Option<Value>>,
+}
+
+impl Debugger {
+    fn new(level: Level, program: Vec<Value>) -> Self {
+        Self {
+            computer: with_history(create_computer(level, program.clone())),
+            program,
+            level,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    /// Execute a single command. Returns false if the debugger should exit
+    fn execute(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return true,
+        };
+        let args = words.collect::<Vec<_>>();
+
+        let result = match command {
+            "s" | "step" => parse_arg(&args, 0, 1).map(|count| self.step(count)),
+            "c" | "continue" => {
+                self.run();
+                Ok(())
+            }
+            "rs" | "rstep" => parse_arg(&args, 0, 1).map(|count| self.step_back(count)),
+            "rc" | "rcontinue" => {
+                self.run_back();
+                Ok(())
+            }
+            "lw" | "lastwrite" => parse_address(&args, 0).map(|address| self.last_write(address)),
+            "b" | "break" => parse_address(&args, 0).map(|address| {
+                self.breakpoints.insert(address);
+            }),
+            "d" | "delete" => parse_address(&args, 0).map(|address| {
+                self.breakpoints.remove(&address);
+            }),
+            "w" | "watch" => parse_address(&args, 0).map(|address| {
+                self.watchpoints.insert(address, self.computer.get(address));
+            }),
+            "u" | "unwatch" => parse_address(&args, 0).map(|address| {
+                self.watchpoints.remove(&address);
+            }),
+            "p" | "print" => parse_address(&args, 0)
+                .and_then(|address| Ok((address, parse_arg(&args, 1, 1)?)))
+                .map(|(address, count)| self.print(address, count)),
+            "set" => parse_address(&args, 0)
+                .and_then(|address| Ok((address, parse_value(&args, 1)?)))
+                .map(|(address, value)| self.computer.set(address, value)),
+            "l" | "list" => parse_arg(&args, 0, self.computer.get_ptr())
+                .and_then(|address| Ok((address, parse_arg(&args, 1, 10)?)))
+                .map(|(address, count)| self.list(address, count)),
+            "i" | "info" => {
+                self.info();
+                Ok(())
+            }
+            "is" | "instructions" => {
+                self.instructions();
+                Ok(())
+            }
+            "cfg" => match args.first() {
+                Some(&"dot") => {
+                    print!("{}", analyze_with(&self.computer, &self.memory()).to_dot());
+                    Ok(())
+                }
+                Some(arg) => Err(format!("Unknown format {}", arg)),
+                None => {
+                    print!("{}", analyze_with(&self.computer, &self.memory()));
+                    Ok(())
+                }
+            },
+            "dc" | "decompile" => {
+                print!("{}", decompile_with(&self.computer, &self.memory()));
+                Ok(())
+            }
+            "in" | "input" => args
+                .iter()
+                .map(|arg| arg.parse::<Value>().map_err(|err| err.to_string()))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|values| self.computer.extend_input(values)),
+            "r" | "reset" => {
+                self.computer = with_history(create_computer(self.level, self.program.clone()));
+                self.update_watchpoints();
+                Ok(())
+            }
+            "save" => args
+                .first()
+                .ok_or_else(|| "Missing path".to_string())
+                .and_then(|path| {
+                    self.computer
+                        .snapshot()
+                        .save(path)
+                        .map_err(|err| err.to_string())
+                }),
+            "load" => args
+                .first()
+                .ok_or_else(|| "Missing path".to_string())
+                .and_then(|path| Snapshot::load(path).map_err(|err| err.to_string()))
+                .map(|snapshot| {
+                    self.computer = with_history(Computer::from_snapshot(&snapshot));
+                    self.update_watchpoints();
+                    self.list(self.computer.get_ptr(), 1);
+                }),
+            "h" | "help" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "q" | "quit" => return false,
+            _ => Err(format!("Unknown command {}. Type help for a list", command)),
+        };
+
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+        true
+    }
+
+    /// Execute one instruction. Returns false if execution should stop
+    fn step_once(&mut self) -> bool {
+        let result = self.computer.step();
+        self.print_output();
+
+        match result {
+            Ok(ComputerState::Runnable) => {}
+            Ok(state) => {
+                println!("Computer stopped with state: {:?}", state);
+                return false;
+            }
+            Err(err) => {
+                println!("Computer threw error: {}", err);
+                return false;
+            }
+        }
+
+        let mut changed = false;
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = self.computer.get(*address);
+            if new != *old {
+                println!(
+                    "Watchpoint {}: {} -> {}",
+                    address,
+                    display_cell(*old),
+                    display_cell(new)
+                );
+                *old = new;
+                changed = true;
+            }
+        }
+        !changed
+    }
+
+    fn step(&mut self, count: usize) {
+        for _ in 0..count {
+            if !self.step_once() {
+                break;
+            }
+        }
+        self.list(self.computer.get_ptr(), 1);
+    }
+
+    fn run(&mut self) {
+        while self.step_once() {
+            if self.breakpoints.contains(&self.computer.get_ptr()) {
+                println!("Breakpoint at {}", self.computer.get_ptr());
+                break;
+            }
+        }
+        self.list(self.computer.get_ptr(), 1);
+    }
+
+    fn step_back(&mut self, count: usize) {
+        for _ in 0..count {
+            if !self.computer.step_back() {
+                println!("Reached start of history");
+                break;
+            }
+        }
+        self.update_watchpoints();
+        self.list(self.computer.get_ptr(), 1);
+    }
+
+    fn run_back(&mut self) {
+        let breakpoints = &self.breakpoints;
+        let watchpoints = &self.watchpoints;
+        let stopped = self.computer.run_back_until(|c| {
+            breakpoints.contains(&c.get_ptr())
+                || watchpoints
+                    .iter()
+                    .any(|(address, value)| c.get(*address) != *value)
+        });
+
+        if !stopped {
+            println!("Reached start of history");
+        } else if self.breakpoints.contains(&self.computer.get_ptr()) {
+            println!("Breakpoint at {}", self.computer.get_ptr());
+        } else {
+            for (address, old) in &self.watchpoints {
+                let new = self.computer.get(*address);
+                if new != *old {
+                    println!(
+                        "Watchpoint {}: {} <- {}",
+                        address,
+                        display_cell(new),
+                        display_cell(*old)
+                    );
+                }
+            }
+        }
+        self.update_watchpoints();
+        self.list(self.computer.get_ptr(), 1);
+    }
+
+    fn last_write(&mut self, address: usize) {
+        if self.computer.run_back_to_write(address) {
+            println!(
+                "Last write to {} at step {}",
+                address,
+                self.computer.get_step_count() + 1
+            );
+        } else {
+            println!("No recorded write to {}", address);
+        }
+        self.update_watchpoints();
+        self.list(self.computer.get_ptr(), 1);
+    }
+
+    fn print(&self, address: usize, count: usize) {
+        let end = match address.checked_add(count) {
+            Some(end) => end,
+            None => {
+                println!("Range out of bounds");
+                return;
+            }
+        };
+        for address in address..end {
+            println!(
+                "{:>5}: {}",
+                address,
+                display_cell(self.computer.get(address))
+            );
+        }
+    }
+
+    fn list(&self, address: usize, count: usize) {
+        let len = match count.checked_mul(4) {
+            Some(len) => len,
+            None => {
+                println!("Too many lines");
+                return;
+            }
+        };
+        let listing = disassemble_computer(&self.computer, address, len);
+
+        for line in listing.get_lines().iter().take(count) {
+            let marker = if line.get_address() == self.computer.get_ptr() {
+                "=>"
+            } else if self.breakpoints.contains(&line.get_address()) {
+                " *"
+            } else {
+                "  "
+            };
+            println!("{} {}", marker, line);
+        }
+    }
+
+    fn info(&self) {
+        println!("Instruction pointer: {}", self.computer.get_ptr());
+        println!("Relative base: {}", self.computer.get_relative_base());
+        println!("State: {:?}", self.computer.get_status());
+        if let Some(err) = self.computer.get_error() {
+            println!("Error: {}", err);
+        }
+        println!("Breakpoints: {:?}", self.breakpoints);
+        println!("Watchpoints: {:?}", self.watchpoints.keys());
+    }
+
+    fn instructions(&self) {
+        let instruction_set = self.computer.get_instruction_set();
+        for code in instruction_set.codes() {
+            println!(
+                "{:>5}: {:<8} arity {}",
+                code,
+                instruction_set.mnemonic(code).unwrap_or("-"),
+                instruction_set.arity(code).unwrap_or(0)
+            );
+        }
+    }
+
+    fn print_output(&mut self) {
+        for value in self.computer.take_output() {
+            println!("Output: {}", value);
+        }
+    }
+
+    /// Current memory of the computer up to the last cell that holds a value
+    fn memory(&self) -> Vec<Value> {
+        (0..self.computer.get_memory_end())
+            .map(|address| self.computer.get(address))
+            .take_while(Option::is_some)
+            .flatten()
+            .collect()
+    }
+
+    fn update_watchpoints(&mut self) {
+        for (address, value) in self.watchpoints.iter_mut() {
+            *value = self.computer.get(*address);
+        }
+    }
+}
+
+/// Enable the undo log for reverse execution
+fn with_history(mut computer: Computer) -> Computer {
+    computer.enable_history(HISTORY_STEPS);
+    computer
+}
+
+fn display_cell(value: Option<Value>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+fn parse_arg(args: &[&str], index: usize, default: usize) -> Result<usize, String> {
+    match args.get(index) {
+        Some(arg) => arg.parse::<usize>().map_err(|err| err.to_string()),
+        None => Ok(default),
+    }
+}
+
+fn parse_address(args: &[&str], index: usize) -> Result<usize, String> {
+    args.get(index)
+        .ok_or_else(|| "Missing address".to_string())?
+        .parse::<usize>()
+        .map_err(|err| err.to_string())
+}
+
+fn parse_value(args: &[&str], index: usize) -> Result<Value, String> {
+    args.get(index)
+        .ok_or_else(|| "Missing value".to_string())?
+        .parse::<Value>()
+        .map_err(|err| err.to_string())
+}
+
+fn main() -> io::Result<()> {
+    let settings = Settings::from_args();
+
+    let program = get_program_from_file(settings.input)?;
+    let mut debugger = Debugger::new(settings.level, program);
+
+    println!("{}", HELP);
+    debugger.list(0, 1);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 || !debugger.execute(&line) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn get_program_from_file<P: AsRef<path::Path>>(file: P) -> io::Result<Vec<Value>> {
+    loader::load(file).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}